@@ -0,0 +1,113 @@
+pub mod registrar;
+
+use crate::tray::{TrayError, TrayMenu, TrayMenuCache};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tauri::{AppHandle, Emitter};
+use tokio::sync::RwLock;
+use zbus::zvariant::OwnedObjectPath;
+
+/// Prefix of the menu cache keys used for application menus.
+pub const APP_MENU_PREFIX: &str = "appmenu:";
+
+/// dbusmenu registered by an application for one of its X11 windows.
+#[derive(Debug, Clone)]
+pub struct AppMenuEntry {
+    /// Unique bus name of the registering client
+    pub service: String,
+    pub menu_path: OwnedObjectPath,
+}
+
+#[derive(Debug, Default)]
+pub struct AppMenuState {
+    pub menus: HashMap<u32, AppMenuEntry>,
+    pub active_window: Option<u32>,
+}
+
+pub type AppMenuRegistry = Arc<RwLock<AppMenuState>>;
+
+pub fn create_app_menu_registry() -> AppMenuRegistry {
+    Arc::new(RwLock::new(AppMenuState::default()))
+}
+
+/// Menu bar of the active window.
+#[derive(Debug, Clone, Serialize)]
+pub struct AppMenu {
+    pub window_id: String,
+    /// Key of the menu in the menu cache and in `tray-menu-update` events
+    pub service_name: String,
+    pub items: Vec<TrayMenu>,
+}
+
+pub fn menu_key(window_id: u32) -> String {
+    format!("{}{}", APP_MENU_PREFIX, window_id)
+}
+
+/// Returns the bus name and menu path registered for `window_id`.
+pub async fn get_window_menu(
+    registry: &AppMenuRegistry,
+    window_id: u32,
+) -> Result<(String, String), TrayError> {
+    let state = registry.read().await;
+    let entry = state
+        .menus
+        .get(&window_id)
+        .ok_or_else(|| TrayError::NoMenu(format!("La ventana {} no tiene menú registrado", window_id)))?;
+
+    Ok((entry.service.clone(), entry.menu_path.to_string()))
+}
+
+/// Removes the menu of `window_id`, returning whether one was registered.
+pub async fn unregister_window(
+    registry: &AppMenuRegistry,
+    menu_cache: &TrayMenuCache,
+    app_handle: &AppHandle,
+    window_id: u32,
+) -> bool {
+    let (removed, is_active) = {
+        let mut state = registry.write().await;
+        let removed = state.menus.remove(&window_id).is_some();
+        (removed, state.active_window == Some(window_id))
+    };
+
+    if removed {
+        println!("[AppMenu] Desregistrando menú de ventana {}", window_id);
+        forget_menu(menu_cache, window_id).await;
+        if is_active {
+            emit_app_menu_changed(app_handle, Some(window_id)).await;
+        }
+    }
+
+    removed
+}
+
+/// Records the focused window, notifying the panel when its menu changes.
+pub async fn set_active_window(registry: &AppMenuRegistry, app_handle: &AppHandle, window_id: Option<u32>) {
+    let changed = {
+        let mut state = registry.write().await;
+        let changed = state.active_window != window_id;
+        state.active_window = window_id;
+        changed
+    };
+
+    if changed {
+        emit_app_menu_changed(app_handle, window_id).await;
+    }
+}
+
+/// Drops the cached menu of a window and stops following its signals.
+pub async fn forget_menu(menu_cache: &TrayMenuCache, window_id: u32) {
+    if let Some(state) = menu_cache.write().await.remove(&menu_key(window_id)) {
+        if let Some(watcher) = state.watcher {
+            watcher.abort();
+        }
+    }
+}
+
+pub async fn emit_app_menu_changed(app_handle: &AppHandle, window_id: Option<u32>) {
+    let window_id = window_id.map(|window_id| window_id.to_string());
+    if let Err(e) = app_handle.emit("app-menu-changed", window_id) {
+        eprintln!("[AppMenu] Error emitiendo evento app-menu-changed: {}", e);
+    }
+}
@@ -0,0 +1,190 @@
+use super::{emit_app_menu_changed, forget_menu, menu_key, unregister_window, AppMenuEntry, AppMenuRegistry};
+use crate::tray::{menu_parser::MenuParser, TrayMenuCache};
+use futures_util::stream::StreamExt;
+use tauri::AppHandle;
+use zbus::{
+    fdo, interface, message::Header, zvariant::{ObjectPath, OwnedObjectPath}, Connection, MatchRule, MessageStream,
+    MessageType, SignalContext,
+};
+
+pub const REGISTRAR_SERVICE: &str = "com.canonical.AppMenu.Registrar";
+pub const REGISTRAR_PATH: &str = "/com/canonical/AppMenu/Registrar";
+
+/// `com.canonical.AppMenu.Registrar`: applications announce the dbusmenu of
+/// each of their X11 windows so the panel can show it as a global menu.
+pub struct AppMenuRegistrar {
+    registry: AppMenuRegistry,
+    menu_cache: TrayMenuCache,
+    app_handle: AppHandle,
+}
+
+#[interface(name = "com.canonical.AppMenu.Registrar")]
+impl AppMenuRegistrar {
+    async fn register_window(
+        &self,
+        window_id: u32,
+        menu_object_path: ObjectPath<'_>,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &Connection,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+    ) -> fdo::Result<()> {
+        let service = header
+            .sender()
+            .ok_or_else(|| fdo::Error::Failed("Remitente desconocido".to_string()))?
+            .to_string();
+        println!("[AppMenu] Registrando menú de ventana {}: {}{}", window_id, service, menu_object_path);
+
+        let is_active = {
+            let mut state = self.registry.write().await;
+            let entry = AppMenuEntry {
+                service: service.clone(),
+                menu_path: OwnedObjectPath::from(menu_object_path.clone()),
+            };
+            state.menus.insert(window_id, entry);
+            state.active_window == Some(window_id)
+        };
+
+        // A re-registered window may point at a different menu
+        forget_menu(&self.menu_cache, window_id).await;
+
+        // Subscribe after replying: the client may be blocked waiting for it.
+        // zbus runs handlers on its own executor, without a tokio reactor
+        tauri::async_runtime::spawn({
+            let connection = connection.clone();
+            let menu_cache = self.menu_cache.clone();
+            let app_handle = self.app_handle.clone();
+            let service = service.clone();
+            let menu_path = menu_object_path.to_string();
+
+            async move {
+                if let Err(e) = MenuParser::watch_menu(
+                    connection,
+                    menu_cache,
+                    app_handle.clone(),
+                    menu_key(window_id),
+                    service,
+                    menu_path,
+                ).await {
+                    eprintln!("[AppMenu] Error suscribiendo al menú de la ventana {}: {}", window_id, e);
+                }
+
+                if is_active {
+                    emit_app_menu_changed(&app_handle, Some(window_id)).await;
+                }
+            }
+        });
+
+        Self::window_registered(&ctxt, window_id, &service, menu_object_path).await?;
+        Ok(())
+    }
+
+    async fn unregister_window(
+        &self,
+        window_id: u32,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+    ) -> fdo::Result<()> {
+        if unregister_window(&self.registry, &self.menu_cache, &self.app_handle, window_id).await {
+            Self::window_unregistered(&ctxt, window_id).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn get_menu_for_window(&self, window_id: u32) -> fdo::Result<(String, OwnedObjectPath)> {
+        let state = self.registry.read().await;
+        let entry = state
+            .menus
+            .get(&window_id)
+            .ok_or_else(|| fdo::Error::InvalidArgs(format!("La ventana {} no tiene menú registrado", window_id)))?;
+
+        Ok((entry.service.clone(), entry.menu_path.clone()))
+    }
+
+    #[zbus(signal)]
+    async fn window_registered(
+        ctxt: &SignalContext<'_>,
+        window_id: u32,
+        service: &str,
+        menu_object_path: ObjectPath<'_>,
+    ) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn window_unregistered(ctxt: &SignalContext<'_>, window_id: u32) -> zbus::Result<()>;
+}
+
+/// Serves the registrar on its own session connection. Returns `false` when
+/// another process already owns the registrar name.
+pub async fn start_registrar(
+    registry: AppMenuRegistry,
+    menu_cache: TrayMenuCache,
+    app_handle: AppHandle,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let connection = Connection::session().await?;
+
+    connection
+        .object_server()
+        .at(
+            REGISTRAR_PATH,
+            AppMenuRegistrar {
+                registry: registry.clone(),
+                menu_cache: menu_cache.clone(),
+                app_handle: app_handle.clone(),
+            },
+        )
+        .await?;
+
+    match connection.request_name(REGISTRAR_SERVICE).await {
+        Ok(()) => {}
+        Err(zbus::Error::NameTaken) => {
+            println!("[AppMenu] {} ya está en uso, no se sirve el menú global", REGISTRAR_SERVICE);
+            return Ok(false);
+        }
+        Err(e) => return Err(e.into()),
+    }
+
+    // Applications do not always unregister before exiting
+    let name_owner_rule = MatchRule::builder()
+        .msg_type(MessageType::Signal)
+        .interface("org.freedesktop.DBus")?
+        .member("NameOwnerChanged")?
+        .build();
+
+    let mut name_stream = MessageStream::for_match_rule(name_owner_rule, &connection, None).await?;
+
+    tokio::spawn(async move {
+        while let Some(Ok(message)) = name_stream.next().await {
+            let body = message.body();
+            let Ok((name, _old_owner, new_owner)) = body.deserialize::<(&str, &str, &str)>() else {
+                continue;
+            };
+            if !new_owner.is_empty() {
+                continue;
+            }
+
+            let windows: Vec<u32> = registry
+                .read()
+                .await
+                .menus
+                .iter()
+                .filter(|(_, entry)| entry.service == name)
+                .map(|(window_id, _)| *window_id)
+                .collect();
+
+            for window_id in windows {
+                if unregister_window(&registry, &menu_cache, &app_handle, window_id).await {
+                    if let Ok(iface_ref) = connection
+                        .object_server()
+                        .interface::<_, AppMenuRegistrar>(REGISTRAR_PATH)
+                        .await
+                    {
+                        if let Err(e) = AppMenuRegistrar::window_unregistered(iface_ref.signal_context(), window_id).await {
+                            eprintln!("[AppMenu] Error emitiendo WindowUnregistered: {}", e);
+                        }
+                    }
+                }
+            }
+        }
+    });
+
+    Ok(true)
+}
@@ -0,0 +1,128 @@
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::{Arc, Mutex};
+use tauri::{AppHandle, Listener, Manager};
+use tauri_plugin_config_manager::ConfigManagerExt;
+
+/// How tray items and windows are ordered. Ties, and `Creation` itself, keep
+/// the order in which entries appeared.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SortMode {
+    #[default]
+    Creation,
+    AppId,
+    Title,
+    Workspace,
+}
+
+/// Last configuration read by `load_config`, dropped whenever the file
+/// changes.
+pub type ConfigCache = Arc<Mutex<Option<Value>>>;
+
+pub fn create_config_cache() -> ConfigCache {
+    Arc::new(Mutex::new(None))
+}
+
+/// Clears the managed `ConfigCache` on every `config-changed` event the
+/// config manager sends when the file is written.
+pub fn watch_config(app_handle: &AppHandle) {
+    let cache = app_handle.state::<ConfigCache>().inner().clone();
+    app_handle.listen("config-changed", move |_| {
+        *cache.lock().unwrap_or_else(|e| e.into_inner()) = None;
+    });
+}
+
+fn cache(app_handle: &AppHandle) -> Option<ConfigCache> {
+    app_handle.try_state::<ConfigCache>().map(|cache| cache.inner().clone())
+}
+
+/// Parses the shared vasak configuration file. A missing file, as on the
+/// first run, reads as an empty configuration.
+pub async fn read_config(app_handle: &AppHandle) -> Result<Value, Box<dyn std::error::Error>> {
+    if !app_handle.config_manager().config_path().exists() {
+        return Ok(json!({}));
+    }
+
+    let contents = app_handle.config_manager().read_config().await?;
+    if contents.trim().is_empty() {
+        return Ok(json!({}));
+    }
+    Ok(serde_json::from_str(&contents)?)
+}
+
+/// Like `read_config`, but served from the `ConfigCache` when possible, and
+/// an unreadable file is logged and read as empty, for callers that fall
+/// back to defaults.
+pub async fn load_config(app_handle: &AppHandle) -> Value {
+    let cache = cache(app_handle);
+    if let Some(config) = cache.as_ref().and_then(|cache| cache.lock().ok()?.clone()) {
+        return config;
+    }
+
+    match read_config(app_handle).await {
+        Ok(config) => {
+            if let Some(cache) = cache {
+                *cache.lock().unwrap_or_else(|e| e.into_inner()) = Some(config.clone());
+            }
+            config
+        }
+        Err(e) => {
+            eprintln!("[Config] Error leyendo la configuración: {}", e);
+            json!({})
+        }
+    }
+}
+
+/// Reads the section at `pointer` (e.g. `/panel/tray`), falling back to the
+/// defaults when the file or the section is missing or invalid.
+pub async fn read_section<T: DeserializeOwned + Default>(app_handle: &AppHandle, pointer: &str) -> T {
+    section(&load_config(app_handle).await, pointer)
+}
+
+/// Reads the section at `pointer` of an already loaded configuration, for
+/// callers that need several.
+pub fn section<T: DeserializeOwned + Default>(config: &Value, pointer: &str) -> T {
+    config
+        .pointer(pointer)
+        .cloned()
+        .and_then(|section| serde_json::from_value(section).ok())
+        .unwrap_or_default()
+}
+
+/// Applies `update` to the configuration and writes it back, keeping every
+/// other setting. A missing file starts empty; an unreadable one is left alone.
+pub async fn update_config(
+    app_handle: &AppHandle,
+    update: impl FnOnce(&mut serde_json::Map<String, Value>) -> Result<(), Box<dyn std::error::Error>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut config = read_config(app_handle).await?;
+
+    update(config.as_object_mut().ok_or("La configuración no es un objeto JSON")?)?;
+
+    if let Some(dir) = app_handle.config_manager().config_path().parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+
+    app_handle
+        .config_manager()
+        .write_config(&serde_json::to_string_pretty(&config)?)
+        .await?;
+
+    if let Some(cache) = cache(app_handle) {
+        *cache.lock().unwrap_or_else(|e| e.into_inner()) = Some(config);
+    }
+    Ok(())
+}
+
+/// Returns the object at `key`, replacing whatever non-object value was there.
+pub fn object_entry<'a>(
+    object: &'a mut serde_json::Map<String, Value>,
+    key: &str,
+) -> &'a mut serde_json::Map<String, Value> {
+    let entry = object.entry(key).or_insert_with(|| json!({}));
+    if !entry.is_object() {
+        *entry = json!({});
+    }
+    entry.as_object_mut().expect("entry was just made an object")
+}
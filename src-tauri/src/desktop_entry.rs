@@ -0,0 +1,428 @@
+use crate::icon_theme::{parse_ini, xdg_data_dirs};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+const DESKTOP_ENTRY_GROUP: &str = "Desktop Entry";
+/// How long `DesktopIndex::current` trusts its index before checking the
+/// desktop files again
+const RECHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// An installed application, from a `.desktop` file of type `Application`.
+#[derive(Debug, Clone)]
+pub struct DesktopEntry {
+    /// Desktop file id: the path below `applications/` with `/` turned into
+    /// `-` and without the `.desktop` suffix
+    pub id: String,
+    /// `Name`, localized when possible
+    pub name: String,
+    pub icon: Option<String>,
+    pub exec: Option<String>,
+    pub startup_wm_class: Option<String>,
+    /// Working directory from `Path`
+    pub working_dir: Option<String>,
+    /// Whether `Terminal` asks for the program to run in a terminal
+    pub terminal: bool,
+    /// The `.desktop` file, for the `%k` field code
+    pub path: PathBuf,
+}
+
+impl DesktopEntry {
+    /// Parses a desktop file, with `Name` localized for `locale` (e.g.
+    /// `es_AR`). Hidden entries and non-applications are skipped.
+    pub fn parse(id: &str, path: &Path, contents: &str, locale: &str) -> Option<Self> {
+        let sections = parse_ini(contents);
+        let group = sections.get(DESKTOP_ENTRY_GROUP)?;
+
+        if group.get("Type").copied() != Some("Application") || group.get("Hidden").copied() == Some("true") {
+            return None;
+        }
+
+        let non_empty = |key: &str| group.get(key).filter(|value| !value.is_empty()).map(|value| value.to_string());
+
+        Some(Self {
+            id: id.to_string(),
+            name: localized_name(group, locale).unwrap_or_else(|| id.to_string()),
+            icon: non_empty("Icon"),
+            exec: non_empty("Exec"),
+            startup_wm_class: non_empty("StartupWMClass"),
+            working_dir: non_empty("Path"),
+            terminal: group.get("Terminal").copied() == Some("true"),
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Basename of the program in `Exec`, skipping an `env VAR=value` prefix.
+    pub fn exec_basename(&self) -> Option<String> {
+        let mut args = self.exec.as_deref()?.split_whitespace().map(|arg| arg.trim_matches('"'));
+        let mut program = args.next()?;
+
+        if basename(program) == "env" {
+            program = args.find(|arg| !arg.contains('='))?;
+        }
+
+        Some(basename(program).to_string())
+    }
+}
+
+fn basename(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+/// The `LC_MESSAGES` locale without encoding or modifier, e.g. `es_AR`.
+fn messages_locale() -> String {
+    let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|value| !value.is_empty())
+        .unwrap_or_default();
+    locale.split(['.', '@']).next().unwrap_or_default().to_string()
+}
+
+/// `Name[lang_COUNTRY]`, `Name[lang]` or `Name`.
+fn localized_name(group: &HashMap<&str, &str>, locale: &str) -> Option<String> {
+    let lang = locale.split('_').next().unwrap_or_default();
+
+    [format!("Name[{}]", locale), format!("Name[{}]", lang)]
+        .iter()
+        .filter(|key| key.len() > "Name[]".len())
+        .find_map(|key| group.get(key.as_str()))
+        .or_else(|| group.get("Name"))
+        .filter(|name| !name.is_empty())
+        .map(|name| name.to_string())
+}
+
+/// Installed applications, indexed by the keys windows are matched on.
+#[derive(Debug, Default)]
+pub struct DesktopIndex {
+    entries: Vec<DesktopEntry>,
+    by_id: HashMap<String, usize>,
+    by_wm_class: HashMap<String, usize>,
+    by_exec: HashMap<String, usize>,
+}
+
+impl DesktopIndex {
+    /// Scans the `applications` directories, in priority order: the first
+    /// file with a given id wins.
+    pub fn load(application_dirs: &[PathBuf]) -> Self {
+        Self::load_localized(application_dirs, &messages_locale())
+    }
+
+    /// Like `load`, with names localized for `locale` instead of the
+    /// environment's.
+    pub fn load_localized(application_dirs: &[PathBuf], locale: &str) -> Self {
+        let mut index = Self::default();
+        for dir in application_dirs {
+            index.scan_dir(dir, dir, locale);
+        }
+        index
+    }
+
+    /// Returns the index for the current XDG directories, rebuilt when a
+    /// desktop file is added, removed or edited, subdirectories included.
+    /// The files are checked at most every `RECHECK_INTERVAL`.
+    pub fn current() -> Arc<DesktopIndex> {
+        struct CurrentIndex {
+            checked: Instant,
+            stamps: Vec<(PathBuf, SystemTime)>,
+            index: Arc<DesktopIndex>,
+        }
+        static CURRENT: Mutex<Option<CurrentIndex>> = Mutex::new(None);
+
+        let mut current = CURRENT.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(current) = current.as_ref().filter(|current| current.checked.elapsed() < RECHECK_INTERVAL) {
+            return current.index.clone();
+        }
+
+        let dirs = application_dirs();
+        let mut stamps = Vec::new();
+        for dir in &dirs {
+            collect_stamps(dir, &mut stamps);
+        }
+
+        let index = match current.take() {
+            Some(current) if current.stamps == stamps => current.index,
+            _ => Arc::new(DesktopIndex::load(&dirs)),
+        };
+        *current = Some(CurrentIndex {
+            checked: Instant::now(),
+            stamps,
+            index: index.clone(),
+        });
+        index
+    }
+
+    fn scan_dir(&mut self, root: &Path, dir: &Path, locale: &str) {
+        let Ok(read_dir) = std::fs::read_dir(dir) else {
+            return;
+        };
+
+        let mut paths: Vec<PathBuf> = read_dir.filter_map(|entry| entry.ok()).map(|entry| entry.path()).collect();
+        paths.sort();
+
+        for path in paths {
+            if path.is_dir() {
+                self.scan_dir(root, &path, locale);
+                continue;
+            }
+            if path.extension().and_then(|ext| ext.to_str()) != Some("desktop") {
+                continue;
+            }
+
+            let Some(id) = path
+                .strip_prefix(root)
+                .ok()
+                .and_then(|relative| relative.to_str())
+                .and_then(|relative| relative.strip_suffix(".desktop"))
+                .map(|relative| relative.replace('/', "-"))
+            else {
+                continue;
+            };
+            if self.by_id.contains_key(&id.to_lowercase()) {
+                continue;
+            }
+
+            let Ok(contents) = std::fs::read_to_string(&path) else {
+                continue;
+            };
+            match DesktopEntry::parse(&id, &path, &contents, locale) {
+                Some(entry) => self.insert(entry),
+                // A hidden entry still shadows the same id in later directories
+                None => {
+                    self.by_id.insert(id.to_lowercase(), usize::MAX);
+                }
+            }
+        }
+    }
+
+    fn insert(&mut self, entry: DesktopEntry) {
+        let position = self.entries.len();
+        self.by_id.insert(entry.id.to_lowercase(), position);
+        if let Some(wm_class) = &entry.startup_wm_class {
+            self.by_wm_class.entry(wm_class.to_lowercase()).or_insert(position);
+        }
+        if let Some(exec) = entry.exec_basename() {
+            self.by_exec.entry(exec.to_lowercase()).or_insert(position);
+        }
+        self.entries.push(entry);
+    }
+
+    /// Looks up an entry by its desktop file id.
+    pub fn get(&self, id: &str) -> Option<&DesktopEntry> {
+        self.by_id.get(&id.to_lowercase()).and_then(|position| self.entries.get(*position))
+    }
+
+    /// Finds the application a window belongs to from its `WM_CLASS` or
+    /// Wayland app_id: `StartupWMClass`, then the desktop file id, then the
+    /// `Exec` basename, then the last part of a reverse-DNS id.
+    pub fn lookup(&self, app_id: &str) -> Option<&DesktopEntry> {
+        let key = app_id.trim().to_lowercase();
+        let key = key.strip_suffix(".desktop").unwrap_or(&key);
+        if key.is_empty() {
+            return None;
+        }
+
+        let find = |map: &HashMap<String, usize>, key: &str| map.get(key).and_then(|position| self.entries.get(*position));
+        let dashed = key.replace(' ', "-");
+
+        find(&self.by_wm_class, key)
+            .or_else(|| find(&self.by_id, key))
+            .or_else(|| find(&self.by_exec, key))
+            .or_else(|| find(&self.by_id, &dashed))
+            // `org.mozilla.firefox` for `firefox.desktop`
+            .or_else(|| key.rsplit_once('.').and_then(|(_, name)| find(&self.by_id, name)))
+            // `nautilus` for `org.gnome.Nautilus.desktop`
+            .or_else(|| {
+                self.entries
+                    .iter()
+                    .find(|entry| entry.id.rsplit('.').next().is_some_and(|name| name.eq_ignore_ascii_case(key)))
+            })
+    }
+}
+
+/// Modification times of `dir`, its subdirectories and the desktop files in
+/// them. A directory's own time only changes when entries are added or
+/// removed, so edited files are stamped too.
+fn collect_stamps(dir: &Path, stamps: &mut Vec<(PathBuf, SystemTime)>) {
+    let Ok(modified) = dir.metadata().and_then(|metadata| metadata.modified()) else {
+        return;
+    };
+    stamps.push((dir.to_path_buf(), modified));
+
+    let Ok(read_dir) = std::fs::read_dir(dir) else {
+        return;
+    };
+    let mut paths: Vec<PathBuf> = read_dir.filter_map(|entry| entry.ok()).map(|entry| entry.path()).collect();
+    paths.sort();
+
+    for path in paths {
+        if path.is_dir() {
+            collect_stamps(&path, stamps);
+        } else if path.extension().and_then(|ext| ext.to_str()) == Some("desktop") {
+            if let Ok(modified) = path.metadata().and_then(|metadata| metadata.modified()) {
+                stamps.push((path, modified));
+            }
+        }
+    }
+}
+
+/// The `applications` directory of every XDG data directory.
+pub fn application_dirs() -> Vec<PathBuf> {
+    xdg_data_dirs().into_iter().map(|dir| dir.join("applications")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    fn write(path: &Path, contents: &str) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+
+    fn app(name: &str, extra: &str) -> String {
+        format!("[Desktop Entry]\nType=Application\nName={}\n{}", name, extra)
+    }
+
+    /// Two `applications` directories, the first taking priority.
+    fn fixture() -> (TempDir, Vec<PathBuf>) {
+        let root = TempDir::new().unwrap();
+        let dirs = vec![root.path().join("home/applications"), root.path().join("usr/applications")];
+        let (home, usr) = (&dirs[0], &dirs[1]);
+
+        write(&usr.join("firefox.desktop"), &app("Firefox", "Exec=firefox %u\nIcon=firefox\n"));
+        write(&usr.join("org.gnome.Nautilus.desktop"), &app("Files", "Exec=gapplication launch org.gnome.Nautilus %U\n"));
+        write(&usr.join("editor.desktop"), &app("Editor", "Exec=editor\nStartupWMClass=ExampleEdit\n"));
+        write(&usr.join("gimp.desktop"), &app("GIMP", "Exec=/usr/bin/gimp-2.10 %U\n"));
+        write(&usr.join("tool.desktop"), &app("Tool", "Exec=env GDK_BACKEND=x11 LANG=C /opt/tool/bin/tool-bin %F\n"));
+        write(&usr.join("kde4/dolphin.desktop"), &app("Dolphin", "Exec=dolphin\n"));
+        write(&usr.join("hidden.desktop"), &app("Hidden", "Exec=hidden\n"));
+        write(&usr.join("link.desktop"), "[Desktop Entry]\nType=Link\nName=Link\nURL=https://example.com\n");
+        write(&usr.join("notes.txt"), &app("Notes", "Exec=notes\n"));
+
+        write(&home.join("hidden.desktop"), &app("Hidden", "Hidden=true\n"));
+        write(&home.join("editor.desktop"), &app("My Editor", "Exec=editor --user\n"));
+
+        (root, dirs)
+    }
+
+    fn lookup_id(index: &DesktopIndex, app_id: &str) -> Option<String> {
+        index.lookup(app_id).map(|entry| entry.id.clone())
+    }
+
+    #[test]
+    fn looks_up_by_startup_wm_class() {
+        let (_root, dirs) = fixture();
+        write(&dirs[1].join("org.example.Writer.desktop"), &app("Writer", "Exec=writer\nStartupWMClass=ExampleWriter\n"));
+        write(&dirs[1].join("other.desktop"), &app("Other", "Exec=other\nStartupWMClass=firefox\n"));
+        let index = DesktopIndex::load(&dirs);
+
+        assert_eq!(lookup_id(&index, "examplewriter").as_deref(), Some("org.example.Writer"));
+        // StartupWMClass wins over the desktop file id
+        assert_eq!(lookup_id(&index, "Firefox").as_deref(), Some("other"));
+    }
+
+    #[test]
+    fn looks_up_by_desktop_file_id() {
+        let (_root, dirs) = fixture();
+        let index = DesktopIndex::load(&dirs);
+
+        assert_eq!(lookup_id(&index, "firefox").as_deref(), Some("firefox"));
+        assert_eq!(lookup_id(&index, "firefox.desktop").as_deref(), Some("firefox"));
+        assert_eq!(lookup_id(&index, "ORG.GNOME.NAUTILUS").as_deref(), Some("org.gnome.Nautilus"));
+    }
+
+    #[test]
+    fn looks_up_by_exec_basename() {
+        let (_root, dirs) = fixture();
+        let index = DesktopIndex::load(&dirs);
+
+        assert_eq!(lookup_id(&index, "gimp-2.10").as_deref(), Some("gimp"));
+        assert_eq!(lookup_id(&index, "tool-bin").as_deref(), Some("tool"));
+        assert_eq!(index.get("tool").and_then(DesktopEntry::exec_basename).as_deref(), Some("tool-bin"));
+    }
+
+    #[test]
+    fn falls_back_on_reverse_dns_ids() {
+        let (_root, dirs) = fixture();
+        let index = DesktopIndex::load(&dirs);
+
+        // `org.mozilla.firefox` for `firefox.desktop`
+        assert_eq!(lookup_id(&index, "org.mozilla.firefox").as_deref(), Some("firefox"));
+        // `nautilus` for `org.gnome.Nautilus.desktop`
+        assert_eq!(lookup_id(&index, "Nautilus").as_deref(), Some("org.gnome.Nautilus"));
+        assert_eq!(lookup_id(&index, "unknown"), None);
+        assert_eq!(lookup_id(&index, "  "), None);
+    }
+
+    #[test]
+    fn earlier_directories_shadow_later_ones() {
+        let (_root, dirs) = fixture();
+        let index = DesktopIndex::load(&dirs);
+
+        assert_eq!(index.get("editor").map(|entry| entry.name.as_str()), Some("My Editor"));
+        // The user's copy has no StartupWMClass, and the system one is shadowed
+        assert_eq!(lookup_id(&index, "ExampleEdit"), None);
+        // A hidden entry removes the application altogether
+        assert!(index.get("hidden").is_none());
+        assert_eq!(lookup_id(&index, "hidden"), None);
+    }
+
+    #[test]
+    fn skips_non_applications() {
+        let (_root, dirs) = fixture();
+        let index = DesktopIndex::load(&dirs);
+
+        assert!(index.get("link").is_none());
+        assert!(index.get("notes").is_none());
+    }
+
+    #[test]
+    fn builds_ids_of_subdirectories_with_dashes() {
+        let (_root, dirs) = fixture();
+        let index = DesktopIndex::load(&dirs);
+
+        let entry = index.get("kde4-dolphin").unwrap();
+        assert_eq!(entry.name, "Dolphin");
+        assert_eq!(entry.path, dirs[1].join("kde4/dolphin.desktop"));
+        assert_eq!(lookup_id(&index, "dolphin").as_deref(), Some("kde4-dolphin"));
+    }
+
+    #[test]
+    fn localizes_names() {
+        let (_root, dirs) = fixture();
+        write(
+            &dirs[1].join("calc.desktop"),
+            "[Desktop Entry]\nType=Application\nName=Calculator\nName[es]=Calculadora\nName[es_AR]=Calculadora AR\nExec=calc\n",
+        );
+
+        let name = |locale: &str| DesktopIndex::load_localized(&dirs, locale).get("calc").unwrap().name.clone();
+        assert_eq!(name("es_AR"), "Calculadora AR");
+        assert_eq!(name("es_MX"), "Calculadora");
+        assert_eq!(name("de_DE"), "Calculator");
+        assert_eq!(name(""), "Calculator");
+    }
+
+    #[test]
+    fn stamps_change_when_nested_files_are_edited() {
+        let (_root, dirs) = fixture();
+        let stamps = |dirs: &[PathBuf]| {
+            let mut stamps = Vec::new();
+            for dir in dirs {
+                collect_stamps(dir, &mut stamps);
+            }
+            stamps
+        };
+        let before = stamps(&dirs);
+        assert!(before.iter().any(|(path, _)| path.ends_with("kde4/dolphin.desktop")));
+
+        // Editing a file in place leaves every directory time untouched
+        let dolphin = std::fs::File::options().write(true).open(dirs[1].join("kde4/dolphin.desktop")).unwrap();
+        dolphin.set_modified(SystemTime::now() + Duration::from_secs(60)).unwrap();
+
+        assert_ne!(stamps(&dirs), before);
+    }
+}
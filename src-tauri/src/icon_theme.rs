@@ -0,0 +1,539 @@
+use gtk::gdk_pixbuf::Pixbuf;
+use image::RgbaImage;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+const FALLBACK_THEME: &str = "hicolor";
+const ICON_EXTENSIONS: [&str; 3] = ["png", "svg", "xpm"];
+const MOVIE_EXTENSIONS: [&str; 1] = ["gif"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DirectoryType {
+    Fixed,
+    Scalable,
+    Threshold,
+}
+
+#[derive(Debug, Clone)]
+struct ThemeDirectory {
+    path: String,
+    size: u32,
+    scale: u32,
+    min_size: u32,
+    max_size: u32,
+    threshold: u32,
+    kind: DirectoryType,
+}
+
+impl ThemeDirectory {
+    fn matches_size(&self, size: u32, scale: u32) -> bool {
+        if self.scale != scale {
+            return false;
+        }
+
+        match self.kind {
+            DirectoryType::Fixed => self.size == size,
+            DirectoryType::Scalable => self.min_size <= size && size <= self.max_size,
+            DirectoryType::Threshold => {
+                self.size.saturating_sub(self.threshold) <= size && size <= self.size + self.threshold
+            }
+        }
+    }
+
+    fn size_distance(&self, size: u32, scale: u32) -> u32 {
+        let wanted = size * scale;
+
+        match self.kind {
+            DirectoryType::Fixed => (self.size * self.scale).abs_diff(wanted),
+            DirectoryType::Scalable => {
+                if wanted < self.min_size * self.scale {
+                    self.min_size * self.scale - wanted
+                } else {
+                    wanted.saturating_sub(self.max_size * self.scale)
+                }
+            }
+            DirectoryType::Threshold => {
+                if wanted < self.size.saturating_sub(self.threshold) * self.scale {
+                    (self.min_size * self.scale).saturating_sub(wanted)
+                } else if wanted > (self.size + self.threshold) * self.scale {
+                    wanted.saturating_sub(self.max_size * self.scale)
+                } else {
+                    0
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct ThemeIndex {
+    name: String,
+    inherits: Vec<String>,
+    directories: Vec<ThemeDirectory>,
+}
+
+impl ThemeIndex {
+    /// Parses an `index.theme` file.
+    fn parse(name: &str, contents: &str) -> Self {
+        let sections = parse_ini(contents);
+        let header = sections.get("Icon Theme");
+        let list = |key: &str| -> Vec<String> {
+            header
+                .and_then(|entries| entries.get(key))
+                .map(|value| {
+                    value
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|s| !s.is_empty())
+                        .map(str::to_string)
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+
+        let inherits = list("Inherits");
+        let mut directory_names = list("Directories");
+        directory_names.extend(list("ScaledDirectories"));
+
+        let mut seen = HashSet::new();
+        let directories = directory_names
+            .into_iter()
+            .filter(|dir| seen.insert(dir.clone()))
+            .filter_map(|dir| {
+                let entries = sections.get(dir.as_str())?;
+                let number = |key: &str| entries.get(key).and_then(|v| v.trim().parse::<u32>().ok());
+
+                let size = number("Size")?;
+                let kind = match entries.get("Type").map(|v| v.trim()) {
+                    Some("Fixed") => DirectoryType::Fixed,
+                    Some("Scalable") => DirectoryType::Scalable,
+                    _ => DirectoryType::Threshold,
+                };
+
+                Some(ThemeDirectory {
+                    size,
+                    scale: number("Scale").unwrap_or(1).max(1),
+                    min_size: number("MinSize").unwrap_or(size),
+                    max_size: number("MaxSize").unwrap_or(size),
+                    threshold: number("Threshold").unwrap_or(2),
+                    kind,
+                    path: dir,
+                })
+            })
+            .collect();
+
+        Self {
+            name: name.to_string(),
+            inherits,
+            directories,
+        }
+    }
+}
+
+/// Freedesktop Icon Theme Specification lookup, shared by the tray and the
+/// window list: the selected theme, everything it inherits from and
+/// `hicolor`, searched across the icon base directories.
+#[derive(Debug, Clone)]
+pub struct IconTheme {
+    themes: Vec<ThemeIndex>,
+    base_dirs: Vec<PathBuf>,
+}
+
+impl IconTheme {
+    /// Loads `theme_name` and its `Inherits` chain from the given base
+    /// directories (in priority order).
+    pub fn new(theme_name: &str, base_dirs: Vec<PathBuf>) -> Self {
+        let mut themes = Vec::new();
+        let mut visited = HashSet::new();
+        Self::load_chain(theme_name, &base_dirs, &mut visited, &mut themes);
+
+        if !visited.contains(FALLBACK_THEME) {
+            Self::load_chain(FALLBACK_THEME, &base_dirs, &mut visited, &mut themes);
+        }
+
+        Self { themes, base_dirs }
+    }
+
+    /// Returns the theme configured for GTK, cached until the setting changes.
+    pub fn current() -> Arc<IconTheme> {
+        static CURRENT: Mutex<Option<(String, Arc<IconTheme>)>> = Mutex::new(None);
+
+        let theme_name = current_theme_name();
+        let mut current = CURRENT.lock().unwrap_or_else(|e| e.into_inner());
+
+        match current.as_ref() {
+            Some((name, theme)) if *name == theme_name => theme.clone(),
+            _ => {
+                let theme = Arc::new(IconTheme::new(&theme_name, default_base_dirs()));
+                *current = Some((theme_name, theme.clone()));
+                theme
+            }
+        }
+    }
+
+    fn load_chain(
+        name: &str,
+        base_dirs: &[PathBuf],
+        visited: &mut HashSet<String>,
+        themes: &mut Vec<ThemeIndex>,
+    ) {
+        if !visited.insert(name.to_string()) {
+            return;
+        }
+
+        let Some(contents) = base_dirs
+            .iter()
+            .find_map(|dir| std::fs::read_to_string(dir.join(name).join("index.theme")).ok())
+        else {
+            return;
+        };
+
+        let index = ThemeIndex::parse(name, &contents);
+        let inherits = index.inherits.clone();
+        themes.push(index);
+
+        for parent in inherits {
+            Self::load_chain(&parent, base_dirs, visited, themes);
+        }
+    }
+
+    /// Finds the file for `icon_name` at `size` and `scale`. `extra_dirs`, as
+    /// with an SNI item's `IconThemePath`, are searched first, as theme roots
+    /// and then as flat icon directories, so an application's private icon
+    /// beats a theme icon of the same name. The base directories follow in
+    /// the same order.
+    pub fn lookup(&self, icon_name: &str, size: u32, scale: u32, extra_dirs: &[PathBuf]) -> Option<PathBuf> {
+        if icon_name.is_empty() {
+            return None;
+        }
+
+        let path = Path::new(icon_name);
+        if path.is_absolute() {
+            return path.is_file().then(|| path.to_path_buf());
+        }
+
+        let extra_dirs: Vec<&PathBuf> = extra_dirs.iter().collect();
+        let base_dirs: Vec<&PathBuf> = self.base_dirs.iter().collect();
+
+        for dirs in [&extra_dirs, &base_dirs] {
+            for theme in &self.themes {
+                if let Some(found) = Self::lookup_in_theme(theme, icon_name, size, scale, dirs) {
+                    return Some(found);
+                }
+            }
+
+            // Unthemed icons directly inside the directories
+            if let Some(found) = dirs.iter().find_map(|dir| Self::find_file(dir, icon_name, &ICON_EXTENSIONS)) {
+                return Some(found);
+            }
+        }
+
+        None
+    }
+
+    /// Finds an animation such as an SNI `AttentionMovieName`: an absolute
+    /// path, or a GIF in any directory of the theme chain.
+    pub fn lookup_movie(&self, movie_name: &str, extra_dirs: &[PathBuf]) -> Option<PathBuf> {
+        if movie_name.is_empty() {
+            return None;
+        }
+
+        let path = Path::new(movie_name);
+        if path.is_absolute() {
+            return path.is_file().then(|| path.to_path_buf());
+        }
+
+        let base_dirs: Vec<&PathBuf> = extra_dirs.iter().chain(self.base_dirs.iter()).collect();
+
+        for theme in &self.themes {
+            for directory in &theme.directories {
+                for base_dir in &base_dirs {
+                    let dir = base_dir.join(&theme.name).join(&directory.path);
+                    if let Some(found) = Self::find_file(&dir, movie_name, &MOVIE_EXTENSIONS) {
+                        return Some(found);
+                    }
+                }
+            }
+        }
+
+        base_dirs
+            .iter()
+            .find_map(|dir| Self::find_file(dir, movie_name, &MOVIE_EXTENSIONS))
+    }
+
+    fn lookup_in_theme(
+        theme: &ThemeIndex,
+        icon_name: &str,
+        size: u32,
+        scale: u32,
+        base_dirs: &[&PathBuf],
+    ) -> Option<PathBuf> {
+        for directory in theme.directories.iter().filter(|dir| dir.matches_size(size, scale)) {
+            for base_dir in base_dirs {
+                let dir = base_dir.join(&theme.name).join(&directory.path);
+                if let Some(found) = Self::find_file(&dir, icon_name, &ICON_EXTENSIONS) {
+                    return Some(found);
+                }
+            }
+        }
+
+        let mut best: Option<(u32, PathBuf)> = None;
+        for directory in &theme.directories {
+            let distance = directory.size_distance(size, scale);
+            if best.as_ref().is_some_and(|(best_distance, _)| distance >= *best_distance) {
+                continue;
+            }
+
+            for base_dir in base_dirs {
+                let dir = base_dir.join(&theme.name).join(&directory.path);
+                if let Some(found) = Self::find_file(&dir, icon_name, &ICON_EXTENSIONS) {
+                    best = Some((distance, found));
+                    break;
+                }
+            }
+        }
+
+        best.map(|(_, path)| path)
+    }
+
+    fn find_file(dir: &Path, icon_name: &str, extensions: &[&str]) -> Option<PathBuf> {
+        extensions
+            .iter()
+            .map(|ext| dir.join(format!("{}.{}", icon_name, ext)))
+            .find(|path| path.is_file())
+    }
+}
+
+/// Renders an icon file to PNG at `size` pixels, rasterizing SVG and scaling
+/// bitmaps whose size does not match.
+pub fn load_icon_png(path: &Path, size: u32) -> Option<Vec<u8>> {
+    let is_png = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("png"));
+    if is_png && image::image_dimensions(path).ok() == Some((size, size)) {
+        return std::fs::read(path).ok();
+    }
+
+    let pixbuf = Pixbuf::from_file_at_scale(path, size as i32, size as i32, true).ok()?;
+    pixbuf.save_to_bufferv("png", &[]).ok()
+}
+
+/// Looks up `icon_name` in the current theme and decodes it for compositing.
+pub fn lookup_icon_image(icon_name: &str, size: u32, scale: u32, extra_dirs: &[PathBuf]) -> Option<RgbaImage> {
+    let path = IconTheme::current().lookup(icon_name, size, scale, extra_dirs)?;
+    let png = load_icon_png(&path, size * scale)?;
+    image::load_from_memory(&png).ok().map(|img| img.to_rgba8())
+}
+
+/// Icon base directories: `$HOME/.icons`, `$XDG_DATA_HOME/icons`,
+/// `$XDG_DATA_DIRS/icons` and `/usr/share/pixmaps`.
+pub fn default_base_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if let Some(home) = std::env::var_os("HOME") {
+        dirs.push(PathBuf::from(home).join(".icons"));
+    }
+
+    dirs.extend(xdg_data_dirs().into_iter().map(|dir| dir.join("icons")));
+    dirs.push(PathBuf::from("/usr/share/pixmaps"));
+    dirs
+}
+
+/// `$XDG_DATA_HOME` followed by `$XDG_DATA_DIRS`, with the spec's defaults.
+pub fn xdg_data_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+
+    let data_home = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")));
+    if let Some(data_home) = data_home {
+        dirs.push(data_home);
+    }
+
+    let data_dirs = std::env::var("XDG_DATA_DIRS")
+        .ok()
+        .filter(|value| !value.is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".to_string());
+    dirs.extend(
+        data_dirs
+            .split(':')
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from),
+    );
+
+    dirs
+}
+
+/// Reads `gtk-icon-theme-name` from the GTK 3/4 `settings.ini` files.
+pub fn current_theme_name() -> String {
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")));
+
+    let mut candidates = Vec::new();
+    if let Some(config_home) = config_home {
+        candidates.push(config_home.join("gtk-3.0/settings.ini"));
+        candidates.push(config_home.join("gtk-4.0/settings.ini"));
+    }
+    candidates.push(PathBuf::from("/etc/gtk-3.0/settings.ini"));
+
+    candidates
+        .iter()
+        .filter_map(|path| std::fs::read_to_string(path).ok())
+        .find_map(|contents| {
+            parse_ini(&contents)
+                .get("Settings")
+                .and_then(|settings| settings.get("gtk-icon-theme-name"))
+                .map(|name| name.trim().trim_matches('"').to_string())
+                .filter(|name| !name.is_empty())
+        })
+        .unwrap_or_else(|| FALLBACK_THEME.to_string())
+}
+
+/// Minimal INI parser: `[section]` headers and `key=value` pairs.
+pub(crate) fn parse_ini(contents: &str) -> HashMap<&str, HashMap<&str, &str>> {
+    let mut sections: HashMap<&str, HashMap<&str, &str>> = HashMap::new();
+    let mut current = "";
+
+    for line in contents.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+
+        if let Some(section) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            current = section;
+            sections.entry(current).or_default();
+        } else if let Some((key, value)) = line.split_once('=') {
+            sections
+                .entry(current)
+                .or_default()
+                .insert(key.trim(), value.trim());
+        }
+    }
+
+    sections
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn write(path: &Path, contents: &str) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+
+    /// A base directory with a `Fixture` theme inheriting from `Parent`, and
+    /// a `hicolor` theme the chain does not name.
+    fn fixture() -> TempDir {
+        let base = TempDir::new().unwrap();
+        let root = base.path();
+
+        write(
+            &root.join("Fixture/index.theme"),
+            "[Icon Theme]\nName=Fixture\nInherits=Parent\nDirectories=16x16/apps,48x48/apps,scalable/apps,24x24/status\n\n\
+             [16x16/apps]\nSize=16\nType=Fixed\n\n\
+             [48x48/apps]\nSize=48\nType=Fixed\n\n\
+             [scalable/apps]\nSize=64\nMinSize=32\nMaxSize=256\nType=Scalable\n\n\
+             [24x24/status]\nSize=24\nThreshold=4\n",
+        );
+        write(&root.join("Parent/index.theme"), "[Icon Theme]\nName=Parent\nDirectories=32x32/apps\n\n[32x32/apps]\nSize=32\nType=Fixed\n");
+        write(&root.join("hicolor/index.theme"), "[Icon Theme]\nName=Hicolor\nDirectories=16x16/apps\n\n[16x16/apps]\nSize=16\nType=Fixed\n");
+
+        base
+    }
+
+    fn lookup(theme: &IconTheme, base: &TempDir, name: &str, size: u32) -> Option<String> {
+        theme
+            .lookup(name, size, 1, &[])
+            .map(|path| path.strip_prefix(base.path()).unwrap().to_string_lossy().into_owned())
+    }
+
+    #[test]
+    fn falls_back_through_inherits_to_hicolor() {
+        let base = fixture();
+        write(&base.path().join("Parent/32x32/apps/parent-only.png"), "");
+        write(&base.path().join("hicolor/16x16/apps/hicolor-only.png"), "");
+        let theme = IconTheme::new("Fixture", vec![base.path().to_path_buf()]);
+
+        assert_eq!(lookup(&theme, &base, "parent-only", 32).as_deref(), Some("Parent/32x32/apps/parent-only.png"));
+        assert_eq!(lookup(&theme, &base, "hicolor-only", 16).as_deref(), Some("hicolor/16x16/apps/hicolor-only.png"));
+        assert_eq!(lookup(&theme, &base, "missing", 16), None);
+    }
+
+    #[test]
+    fn prefers_the_selected_theme_over_its_parents() {
+        let base = fixture();
+        write(&base.path().join("Fixture/16x16/apps/app.png"), "");
+        write(&base.path().join("Parent/32x32/apps/app.png"), "");
+        let theme = IconTheme::new("Fixture", vec![base.path().to_path_buf()]);
+
+        // An inexact size in the selected theme beats an exact one in a parent
+        assert_eq!(lookup(&theme, &base, "app", 32).as_deref(), Some("Fixture/16x16/apps/app.png"));
+    }
+
+    #[test]
+    fn picks_the_closest_size() {
+        let base = fixture();
+        write(&base.path().join("Fixture/16x16/apps/app.png"), "");
+        write(&base.path().join("Fixture/48x48/apps/app.png"), "");
+        let theme = IconTheme::new("Fixture", vec![base.path().to_path_buf()]);
+
+        assert_eq!(lookup(&theme, &base, "app", 16).as_deref(), Some("Fixture/16x16/apps/app.png"));
+        assert_eq!(lookup(&theme, &base, "app", 20).as_deref(), Some("Fixture/16x16/apps/app.png"));
+        assert_eq!(lookup(&theme, &base, "app", 40).as_deref(), Some("Fixture/48x48/apps/app.png"));
+        assert_eq!(lookup(&theme, &base, "app", 512).as_deref(), Some("Fixture/48x48/apps/app.png"));
+    }
+
+    #[test]
+    fn matches_scalable_and_threshold_directories() {
+        let base = fixture();
+        write(&base.path().join("Fixture/16x16/apps/app.png"), "");
+        write(&base.path().join("Fixture/scalable/apps/app.svg"), "");
+        write(&base.path().join("Fixture/16x16/apps/status.png"), "");
+        write(&base.path().join("Fixture/24x24/status/status.png"), "");
+        let theme = IconTheme::new("Fixture", vec![base.path().to_path_buf()]);
+
+        // 32..=256 fall inside the scalable range
+        assert_eq!(lookup(&theme, &base, "app", 100).as_deref(), Some("Fixture/scalable/apps/app.svg"));
+        assert_eq!(lookup(&theme, &base, "app", 16).as_deref(), Some("Fixture/16x16/apps/app.png"));
+        // 20..=28 fall inside the threshold of the 24px directory
+        assert_eq!(lookup(&theme, &base, "status", 21).as_deref(), Some("Fixture/24x24/status/status.png"));
+        assert_eq!(lookup(&theme, &base, "status", 28).as_deref(), Some("Fixture/24x24/status/status.png"));
+    }
+
+    #[test]
+    fn falls_back_to_unthemed_pixmaps() {
+        let base = fixture();
+        let pixmaps = TempDir::new().unwrap();
+        write(&pixmaps.path().join("legacy.xpm"), "");
+        let theme = IconTheme::new("Fixture", vec![base.path().to_path_buf(), pixmaps.path().to_path_buf()]);
+
+        assert_eq!(theme.lookup("legacy", 24, 1, &[]), Some(pixmaps.path().join("legacy.xpm")));
+    }
+
+    #[test]
+    fn searches_extra_dirs_first() {
+        let base = fixture();
+        let extra = TempDir::new().unwrap();
+        write(&base.path().join("Fixture/16x16/apps/app.png"), "");
+        write(&extra.path().join("app.png"), "");
+        write(&extra.path().join("Fixture/16x16/apps/app.png"), "");
+        let theme = IconTheme::new("Fixture", vec![base.path().to_path_buf()]);
+
+        assert_eq!(theme.lookup("app", 16, 1, &[extra.path().to_path_buf()]), Some(extra.path().join("Fixture/16x16/apps/app.png")));
+    }
+
+    #[test]
+    fn prefers_flat_extra_dirs_over_system_themes() {
+        let base = fixture();
+        let extra = TempDir::new().unwrap();
+        write(&base.path().join("Fixture/16x16/apps/app.png"), "");
+        write(&extra.path().join("app.png"), "");
+        let theme = IconTheme::new("Fixture", vec![base.path().to_path_buf()]);
+
+        assert_eq!(theme.lookup("app", 16, 1, &[extra.path().to_path_buf()]), Some(extra.path().join("app.png")));
+        assert_eq!(theme.lookup("app", 16, 1, &[]), Some(base.path().join("Fixture/16x16/apps/app.png")));
+    }
+}
@@ -0,0 +1,28 @@
+use serde::Serialize;
+use std::fmt;
+
+/// Launcher failure, sent to the frontend as `{ kind, message }`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", content = "message", rename_all = "kebab-case")]
+pub enum LauncherError {
+    /// No installed desktop entry has the requested id
+    NotFound(String),
+    /// The entry has no `Exec` line, or one that doesn't follow the spec
+    InvalidExec(String),
+    Spawn(String),
+    /// Reading or writing the pinned list failed
+    Config(String),
+}
+
+impl fmt::Display for LauncherError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound(message)
+            | Self::InvalidExec(message)
+            | Self::Spawn(message)
+            | Self::Config(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for LauncherError {}
@@ -0,0 +1,390 @@
+use super::LauncherError;
+use crate::desktop_entry::DesktopEntry;
+use std::ffi::OsStr;
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+
+/// Builds the command lines that open `files` with `entry`, following the
+/// Desktop Entry spec: `%F` and `%U` take every file in one process, `%f`
+/// and `%u` start one process per file, and entries without either ignore
+/// the files.
+pub fn build_commands(entry: &DesktopEntry, files: &[String]) -> Result<Vec<Vec<String>>, LauncherError> {
+    let exec = entry
+        .exec
+        .as_deref()
+        .ok_or_else(|| LauncherError::InvalidExec(format!("{} no tiene línea Exec", entry.id)))?;
+    let args = split_exec(&unescape_string(exec))
+        .ok_or_else(|| LauncherError::InvalidExec(format!("Línea Exec inválida en {}: {}", entry.id, exec)))?;
+    if args.is_empty() {
+        return Err(LauncherError::InvalidExec(format!("Línea Exec vacía en {}", entry.id)));
+    }
+
+    let takes_list = args.iter().any(|arg| arg == "%F" || arg == "%U");
+    let takes_one = args.iter().any(|arg| arg.contains("%f") || arg.contains("%u"));
+
+    let batches: Vec<&[String]> = if takes_list || files.is_empty() {
+        vec![files]
+    } else if takes_one {
+        files.chunks(1).collect()
+    } else {
+        vec![&[]]
+    };
+
+    batches
+        .into_iter()
+        .map(|batch| expand_field_codes(entry, &args, batch))
+        .collect()
+}
+
+/// Replaces the field codes of one command line with `files`, the entry's
+/// icon, name and path. Deprecated codes are dropped.
+fn expand_field_codes(entry: &DesktopEntry, args: &[String], files: &[String]) -> Result<Vec<String>, LauncherError> {
+    let invalid = |code: char| LauncherError::InvalidExec(format!("Código %{} inválido en el Exec de {}", code, entry.id));
+    let mut command = Vec::new();
+
+    for arg in args {
+        match arg.as_str() {
+            "%F" => command.extend(files.iter().map(|file| file_path(file))),
+            "%U" => command.extend(files.iter().cloned()),
+            "%i" => {
+                if let Some(icon) = &entry.icon {
+                    command.push("--icon".to_string());
+                    command.push(icon.clone());
+                }
+            }
+            // A lone %f or %u without a file disappears instead of leaving ""
+            "%f" | "%u" if files.is_empty() => {}
+            _ => {
+                let mut expanded = String::new();
+                let mut chars = arg.chars();
+                while let Some(c) = chars.next() {
+                    if c != '%' {
+                        expanded.push(c);
+                        continue;
+                    }
+
+                    match chars.next() {
+                        Some('%') => expanded.push('%'),
+                        Some('f') => expanded.push_str(&files.first().map(|file| file_path(file)).unwrap_or_default()),
+                        Some('u') => expanded.push_str(files.first().map(String::as_str).unwrap_or_default()),
+                        Some('c') => expanded.push_str(&entry.name),
+                        Some('k') => expanded.push_str(&entry.path.to_string_lossy()),
+                        Some('d' | 'D' | 'n' | 'N' | 'v' | 'm') => {}
+                        // %F, %U and %i are only valid as whole arguments
+                        Some(code) => return Err(invalid(code)),
+                        None => return Err(invalid(' ')),
+                    }
+                }
+                command.push(expanded);
+            }
+        }
+    }
+
+    Ok(command)
+}
+
+/// Undoes the escapes every desktop entry string value may contain.
+fn unescape_string(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('s') => unescaped.push(' '),
+            Some('n') => unescaped.push('\n'),
+            Some('t') => unescaped.push('\t'),
+            Some('r') => unescaped.push('\r'),
+            Some('\\') => unescaped.push('\\'),
+            Some(other) => {
+                unescaped.push('\\');
+                unescaped.push(other);
+            }
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+/// Splits an `Exec` value into arguments. Double quotes group an argument,
+/// and inside them a backslash escapes `"`, `` ` ``, `$` and `\`. Returns
+/// `None` for an unterminated quote.
+fn split_exec(exec: &str) -> Option<Vec<String>> {
+    let mut args = Vec::new();
+    let mut current: Option<String> = None;
+    let mut chars = exec.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                let arg = current.get_or_insert_with(String::new);
+                loop {
+                    match chars.next()? {
+                        '"' => break,
+                        '\\' => match chars.next()? {
+                            escaped @ ('"' | '`' | '$' | '\\') => arg.push(escaped),
+                            other => {
+                                arg.push('\\');
+                                arg.push(other);
+                            }
+                        },
+                        other => arg.push(other),
+                    }
+                }
+            }
+            ' ' | '\t' | '\n' => {
+                if let Some(arg) = current.take() {
+                    args.push(arg);
+                }
+            }
+            '\\' => {
+                // Outside quotes a backslash only escapes the next character
+                if let Some(escaped) = chars.next() {
+                    current.get_or_insert_with(String::new).push(escaped);
+                }
+            }
+            other => current.get_or_insert_with(String::new).push(other),
+        }
+    }
+
+    args.extend(current);
+    Some(args)
+}
+
+/// Turns a `file://` URL into a local path; anything else is kept as is.
+fn file_path(file: &str) -> String {
+    let Some(path) = file.strip_prefix("file://") else {
+        return file.to_string();
+    };
+    // Skip the host part, normally empty or `localhost`
+    let path = path.find('/').map_or("", |start| &path[start..]);
+
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Starts `command` in its own process group, detached from the panel's
+/// stdio, and reaps it in the background so it never lingers as a zombie.
+pub fn spawn_detached(command: &[String], working_dir: Option<&str>) -> Result<(), LauncherError> {
+    let (program, args) = command
+        .split_first()
+        .ok_or_else(|| LauncherError::InvalidExec("Comando vacío".to_string()))?;
+
+    let mut process = Command::new(program);
+    process
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .process_group(0);
+    if let Some(dir) = working_dir.filter(|dir| std::path::Path::new(dir).is_dir()) {
+        process.current_dir(dir);
+    }
+
+    let mut child = process
+        .spawn()
+        .map_err(|e| LauncherError::Spawn(format!("Error iniciando {}: {}", program, e)))?;
+    std::thread::spawn(move || {
+        let _ = child.wait();
+    });
+
+    Ok(())
+}
+
+/// Terminals tried when `$TERMINAL` is not set, with the arguments that make
+/// each run the command that follows.
+const TERMINALS: [(&str, &[&str]); 10] = [
+    ("xdg-terminal-exec", &[]),
+    ("x-terminal-emulator", &["-e"]),
+    ("konsole", &["-e"]),
+    ("gnome-terminal", &["--"]),
+    ("xfce4-terminal", &["-x"]),
+    ("alacritty", &["-e"]),
+    ("kitty", &[]),
+    ("foot", &[]),
+    ("wezterm", &["start", "--"]),
+    ("xterm", &["-e"]),
+];
+
+/// Prefixes `command` with the user's terminal, for `Terminal=true` entries.
+pub fn in_terminal(command: Vec<String>) -> Vec<String> {
+    let terminal = std::env::var("TERMINAL").ok();
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    terminal_prefix(terminal.as_deref(), &path).into_iter().chain(command).collect()
+}
+
+/// `$TERMINAL -e`, or the first of `TERMINALS` found in `path`. Falls back
+/// to `xterm -e`, so the spawn error names a terminal.
+fn terminal_prefix(terminal: Option<&str>, path: &OsStr) -> Vec<String> {
+    if let Some(terminal) = terminal.filter(|terminal| !terminal.is_empty()) {
+        return vec![terminal.to_string(), "-e".to_string()];
+    }
+
+    let (program, args) = TERMINALS
+        .iter()
+        .find(|(program, _)| std::env::split_paths(path).any(|dir| dir.join(program).is_file()))
+        .unwrap_or(&("xterm", &["-e"]));
+
+    std::iter::once(program).chain(args.iter()).map(|arg| arg.to_string()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn entry(exec: &str) -> DesktopEntry {
+        DesktopEntry {
+            id: "org.example.App".to_string(),
+            name: "Example App".to_string(),
+            icon: Some("example".to_string()),
+            exec: Some(exec.to_string()),
+            startup_wm_class: None,
+            working_dir: None,
+            terminal: false,
+            path: PathBuf::from("/usr/share/applications/org.example.App.desktop"),
+        }
+    }
+
+    fn commands(exec: &str, files: &[&str]) -> Vec<Vec<String>> {
+        let files: Vec<String> = files.iter().map(|file| file.to_string()).collect();
+        build_commands(&entry(exec), &files).unwrap()
+    }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn splits_on_whitespace_and_quotes() {
+        assert_eq!(split_exec("app  --flag\targ"), Some(args(&["app", "--flag", "arg"])));
+        assert_eq!(split_exec(r#"app "two words" x"#), Some(args(&["app", "two words", "x"])));
+        assert_eq!(split_exec(r#"app pre"quoted part"post"#), Some(args(&["app", "prequoted partpost"])));
+        assert_eq!(split_exec(r#"app """#), Some(args(&["app", ""])));
+        assert_eq!(split_exec(r#"app "unterminated"#), None);
+    }
+
+    #[test]
+    fn unescapes_inside_quotes() {
+        assert_eq!(
+            split_exec(r#"sh -c "echo \"hi\" \$HOME \`date\` \\ \n""#),
+            Some(args(&["sh", "-c", r#"echo "hi" $HOME `date` \ \n"#])),
+        );
+        assert_eq!(split_exec(r"app one\ arg"), Some(args(&["app", "one arg"])));
+    }
+
+    #[test]
+    fn unescapes_string_values() {
+        assert_eq!(unescape_string(r"a\sb\tc\nd\re"), "a b\tc\nd\re");
+        assert_eq!(unescape_string(r"a\\s"), r"a\s");
+        assert_eq!(unescape_string(r"keep \$ and \"), r"keep \$ and \");
+    }
+
+    #[test]
+    fn applies_both_escaping_levels() {
+        // `\\$` in the file is `\$` after the string level and `$` inside quotes
+        assert_eq!(commands(r#"sh -c "echo \\$HOME""#, &[]), vec![args(&["sh", "-c", "echo $HOME"])]);
+    }
+
+    #[test]
+    fn expands_single_file_codes_once_per_file() {
+        assert_eq!(
+            commands("app %f", &["/tmp/a.txt", "file:///tmp/b%20c.txt"]),
+            vec![args(&["app", "/tmp/a.txt"]), args(&["app", "/tmp/b c.txt"])],
+        );
+        assert_eq!(
+            commands("app --open=%u", &["https://example.com/a%20b"]),
+            vec![args(&["app", "--open=https://example.com/a%20b"])],
+        );
+    }
+
+    #[test]
+    fn expands_list_codes_in_one_command() {
+        assert_eq!(
+            commands("app %F", &["file://localhost/tmp/a", "/tmp/b"]),
+            vec![args(&["app", "/tmp/a", "/tmp/b"])],
+        );
+        assert_eq!(
+            commands("app %U --new", &["https://a.example", "file:///tmp/b"]),
+            vec![args(&["app", "https://a.example", "file:///tmp/b", "--new"])],
+        );
+    }
+
+    #[test]
+    fn drops_file_codes_without_files() {
+        assert_eq!(commands("app %f", &[]), vec![args(&["app"])]);
+        assert_eq!(commands("app %U", &[]), vec![args(&["app"])]);
+        assert_eq!(commands("app --file=%f", &[]), vec![args(&["app", "--file="])]);
+    }
+
+    #[test]
+    fn ignores_files_without_file_codes() {
+        assert_eq!(commands("app --new-window", &["/tmp/a"]), vec![args(&["app", "--new-window"])]);
+    }
+
+    #[test]
+    fn expands_entry_codes() {
+        assert_eq!(commands("app %i", &[]), vec![args(&["app", "--icon", "example"])]);
+        assert_eq!(commands("app --name=%c", &[]), vec![args(&["app", "--name=Example App"])]);
+        assert_eq!(
+            commands("app %k", &[]),
+            vec![args(&["app", "/usr/share/applications/org.example.App.desktop"])],
+        );
+
+        let mut without_icon = entry("app %i");
+        without_icon.icon = None;
+        assert_eq!(build_commands(&without_icon, &[]).unwrap(), vec![args(&["app"])]);
+    }
+
+    #[test]
+    fn keeps_literal_percent_and_drops_deprecated_codes() {
+        assert_eq!(commands("app 100%% %d%D%n%N%v%m", &[]), vec![args(&["app", "100%", ""])]);
+    }
+
+    #[test]
+    fn rejects_invalid_exec_lines() {
+        assert!(matches!(build_commands(&entry("app %z"), &[]), Err(LauncherError::InvalidExec(_))));
+        assert!(matches!(build_commands(&entry("app --x=%F"), &[]), Err(LauncherError::InvalidExec(_))));
+        assert!(matches!(build_commands(&entry("app 50%"), &[]), Err(LauncherError::InvalidExec(_))));
+        assert!(matches!(build_commands(&entry(r#"app "open"#), &[]), Err(LauncherError::InvalidExec(_))));
+        assert!(matches!(build_commands(&entry("   "), &[]), Err(LauncherError::InvalidExec(_))));
+
+        let mut without_exec = entry("");
+        without_exec.exec = None;
+        assert!(matches!(build_commands(&without_exec, &[]), Err(LauncherError::InvalidExec(_))));
+    }
+
+    #[test]
+    fn picks_a_terminal() {
+        let bin = tempfile::TempDir::new().unwrap();
+        std::fs::write(bin.path().join("konsole"), "").unwrap();
+        std::fs::write(bin.path().join("xterm"), "").unwrap();
+        let path = std::env::join_paths([PathBuf::from("/nonexistent"), bin.path().to_path_buf()]).unwrap();
+
+        assert_eq!(terminal_prefix(Some("myterm"), &path), args(&["myterm", "-e"]));
+        assert_eq!(terminal_prefix(Some(""), &path), args(&["konsole", "-e"]));
+        assert_eq!(terminal_prefix(None, &path), args(&["konsole", "-e"]));
+        assert_eq!(terminal_prefix(None, OsStr::new("")), args(&["xterm", "-e"]));
+    }
+}
@@ -0,0 +1,128 @@
+pub mod error;
+pub mod exec;
+
+use crate::config;
+use crate::desktop_entry::DesktopIndex;
+use crate::window_manager::{WindowInfo, WindowList};
+use serde::Serialize;
+use serde_json::Value;
+use tauri::AppHandle;
+
+pub use error::LauncherError;
+
+/// Desktop ids of the pinned applications, in the order they are shown.
+const PINNED_POINTER: &str = "/panel/launchers/pinned";
+
+/// A pinned application and the windows it currently has open.
+#[derive(Debug, Clone, Serialize)]
+pub struct Launcher {
+    pub desktop_id: String,
+    pub name: String,
+    pub icon: Option<String>,
+    /// Whether any window of the application is open
+    pub running: bool,
+    pub any_active: bool,
+    pub windows: Vec<WindowInfo>,
+}
+
+/// What `get_windows` returns: the pinned applications, then the windows no
+/// pin claimed.
+#[derive(Debug, Clone, Serialize)]
+pub struct Taskbar {
+    pub launchers: Vec<Launcher>,
+    pub windows: WindowList,
+}
+
+/// Desktop ids of the pinned applications in a loaded configuration.
+pub fn pinned(config: &Value) -> Vec<String> {
+    config::section(config, PINNED_POINTER)
+}
+
+/// Pins an application at `position` (the end by default), or unpins it.
+pub async fn set_pinned(
+    app_handle: &AppHandle,
+    desktop_id: &str,
+    pinned: bool,
+    position: Option<usize>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    config::update_config(app_handle, |config| {
+        let launchers = config::object_entry(config::object_entry(config, "panel"), "launchers");
+
+        let mut ids: Vec<String> = launchers
+            .get("pinned")
+            .cloned()
+            .and_then(|ids| serde_json::from_value(ids).ok())
+            .unwrap_or_default();
+
+        ids.retain(|id| id != desktop_id);
+        if pinned {
+            let position = position.unwrap_or(ids.len()).min(ids.len());
+            ids.insert(position, desktop_id.to_string());
+        }
+
+        launchers.insert(
+            "pinned".to_string(),
+            Value::Array(ids.into_iter().map(Value::String).collect()),
+        );
+        Ok(())
+    })
+    .await
+}
+
+/// Pairs each pinned application with its windows. Returns the launchers in
+/// pin order and the windows no pin claimed, in their original order. Pins
+/// of applications that are no longer installed are skipped.
+pub fn merge_pinned(
+    pinned: &[String],
+    index: &DesktopIndex,
+    windows: Vec<WindowInfo>,
+) -> (Vec<Launcher>, Vec<WindowInfo>) {
+    let mut launchers: Vec<Launcher> = pinned
+        .iter()
+        .filter_map(|id| index.get(id))
+        .map(|entry| Launcher {
+            desktop_id: entry.id.clone(),
+            name: entry.name.clone(),
+            icon: entry.icon.clone(),
+            running: false,
+            any_active: false,
+            windows: Vec::new(),
+        })
+        .collect();
+
+    let mut unpinned = Vec::new();
+    for window in windows {
+        let launcher = window
+            .desktop_id
+            .as_deref()
+            .and_then(|id| launchers.iter_mut().find(|launcher| launcher.desktop_id == id));
+
+        match launcher {
+            Some(launcher) => {
+                launcher.running = true;
+                launcher.any_active |= window.is_active;
+                launcher.windows.push(window);
+            }
+            None => unpinned.push(window),
+        }
+    }
+
+    (launchers, unpinned)
+}
+
+/// Starts the application `desktop_id`, opening `files` (paths or URLs)
+/// if its `Exec` line accepts them.
+pub fn launch_app(desktop_id: &str, files: &[String]) -> Result<(), LauncherError> {
+    let index = DesktopIndex::current();
+    let entry = index
+        .get(desktop_id)
+        .ok_or_else(|| LauncherError::NotFound(format!("Aplicación {} no encontrada", desktop_id)))?;
+
+    for command in exec::build_commands(entry, files)? {
+        let command = if entry.terminal { exec::in_terminal(command) } else { command };
+        println!("[Launcher] Iniciando {}: {:?}", desktop_id, command);
+        exec::spawn_detached(&command, entry.working_dir.as_deref())?;
+    }
+
+    Ok(())
+}
@@ -1,13 +1,34 @@
+mod app_menu;
+mod config;
+mod desktop_entry;
+mod icon_theme;
+mod launcher;
 mod window_manager;
 mod tray;
 
-use tray::{sni_watcher::SniWatcher, TrayManager, TrayItem, TrayMenu, create_tray_manager};
+use tray::{
+    menu_parser::MenuParser, panel_item, policy::{self, TrayItemList, TrayItemPolicy, TrayVisibility},
+    sni_watcher::{create_sni_watcher_handle, start_sni_watcher, stop_sni_watcher, SniWatcherHandle, SniWatcherStatus},
+    TrayError, TrayManager, TrayMenuCache, TrayMenu, TrayStatus, create_tray_manager,
+    create_tray_menu_cache, emit_tray_update, get_item_menu, activate_item, secondary_activate_item,
+    item_context_menu, scroll_item,
+};
+#[cfg(feature = "x11")]
+use tray::xembed;
 use std::sync::mpsc::channel;
 use std::sync::{Arc, Mutex};
 use tauri::{Manager, Emitter, generate_context};
 use tauri_plugin_positioner::{Position, WindowExt};
 use tauri_plugin_config_manager;
-use window_manager::{WindowInfo, WindowManager};
+use app_menu::{registrar, AppMenu, AppMenuRegistry, create_app_menu_registry, get_window_menu, menu_key};
+use config::SortMode;
+use desktop_entry::DesktopIndex;
+use launcher::{LauncherError, Taskbar};
+use window_manager::{
+    group_windows, sort_windows, WindowAction, WindowCapabilities, WindowInfo, WindowList, WindowManager,
+    WindowManagerError,
+};
+use zbus::Connection;
 
 // Estado principal de la aplicación
 struct AppState {
@@ -18,90 +39,407 @@ struct AppState {
 async fn init_sni_watcher(
     app_handle: tauri::AppHandle,
     tray_manager: tauri::State<'_, TrayManager>,
-) -> Result<(), String> {
-    let manager = tray_manager.inner().clone();
-    let watcher = SniWatcher::new(manager, app_handle)
-        .await
-        .map_err(|e| format!("Error inicializando SNI watcher: {}", e))?;
-    
-    watcher
-        .start_watching()
-        .await
-        .map_err(|e| format!("Error iniciando watcher: {}", e))?;
-    
-    Ok(())
+    menu_cache: tauri::State<'_, TrayMenuCache>,
+    sni_watcher: tauri::State<'_, SniWatcherHandle>,
+) -> Result<SniWatcherStatus, TrayError> {
+    // Normally already started from setup; this only retries after a failure
+    let status = start_sni_watcher(
+        &sni_watcher,
+        tray_manager.inner().clone(),
+        menu_cache.inner().clone(),
+        app_handle,
+    )
+    .await;
+
+    match status {
+        SniWatcherStatus::Failed(message) => Err(TrayError::DBus(message)),
+        status => Ok(status),
+    }
+}
+
+#[tauri::command]
+async fn tray_status(sni_watcher: tauri::State<'_, SniWatcherHandle>) -> Result<SniWatcherStatus, TrayError> {
+    Ok(sni_watcher.lock().await.status.clone())
+}
+
+#[tauri::command]
+async fn init_xembed_tray(
+    app_handle: tauri::AppHandle,
+    tray_manager: tauri::State<'_, TrayManager>,
+) -> Result<bool, TrayError> {
+    #[cfg(feature = "x11")]
+    if std::env::var("DISPLAY").is_ok() {
+        let started = xembed::start_xembed_tray(tray_manager.inner().clone(), app_handle)
+            .map_err(|e| TrayError::ProtocolUnavailable(format!("Error iniciando bandeja XEmbed: {}", e)));
+        if let Err(e) = &started {
+            panel_item::report_error(&e.to_string()).await;
+        }
+        return started;
+    }
+
+    let _ = (app_handle, tray_manager);
+    Ok(false)
 }
 
 #[tauri::command]
 async fn get_tray_items(
+    sort: Option<SortMode>,
+    app_handle: tauri::AppHandle,
     tray_manager: tauri::State<'_, TrayManager>,
-) -> Result<Vec<TrayItem>, String> {
-    let manager = tray_manager.read().await;
-    Ok(manager.values().cloned().collect())
+) -> Result<TrayItemList, TrayError> {
+    let items = tray_manager.read().await.values().cloned().collect();
+    let policy = policy::load_policy(&app_handle).await;
+    let sort = sort.unwrap_or(policy.sort);
+    Ok(policy.apply(items, sort))
 }
 
 #[tauri::command]
-async fn tray_item_activate(
-    _service_name: String,
-    _x: i32,
-    _y: i32,
-) -> Result<(), String> {
-    // Implementation for activating tray item
+async fn set_tray_item_policy(
+    id: String,
+    visibility: TrayVisibility,
+    order: Option<i32>,
+    app_handle: tauri::AppHandle,
+) -> Result<(), TrayError> {
+    policy::set_item_policy(&app_handle, &id, TrayItemPolicy { visibility, order })
+        .await
+        .map_err(|e| TrayError::Config(format!("Error guardando la política de {}: {}", id, e)))?;
+
+    emit_tray_update(&app_handle).await;
     Ok(())
 }
 
+/// Sets the status published through the panel's own tray item.
+#[tauri::command]
+async fn set_panel_status(status: TrayStatus, message: String, icon_name: Option<String>) -> Result<(), TrayError> {
+    panel_item::set_status(status, icon_name.as_deref(), &message).await
+}
+
+#[tauri::command]
+async fn tray_item_activate(
+    service_name: String,
+    x: i32,
+    y: i32,
+    tray_manager: tauri::State<'_, TrayManager>,
+) -> Result<(), TrayError> {
+    #[cfg(feature = "x11")]
+    if xembed::is_xembed_item(&service_name) {
+        return xembed::send_click(&service_name, 1, x, y);
+    }
+
+    activate_item(&tray_manager, &service_name, x, y).await
+}
+
 #[tauri::command]
 async fn tray_item_secondary_activate(
-    _service_name: String,
-    _x: i32,
-    _y: i32,
-) -> Result<(), String> {
-    // Implementation for secondary activation
-    Ok(())
+    service_name: String,
+    x: i32,
+    y: i32,
+    tray_manager: tauri::State<'_, TrayManager>,
+) -> Result<(), TrayError> {
+    #[cfg(feature = "x11")]
+    if xembed::is_xembed_item(&service_name) {
+        return xembed::send_click(&service_name, 2, x, y);
+    }
+
+    secondary_activate_item(&tray_manager, &service_name, x, y).await
+}
+
+/// Asks an item without a dbusmenu to show its own context menu.
+#[tauri::command]
+async fn tray_item_context_menu(
+    service_name: String,
+    x: i32,
+    y: i32,
+    tray_manager: tauri::State<'_, TrayManager>,
+) -> Result<(), TrayError> {
+    #[cfg(feature = "x11")]
+    if xembed::is_xembed_item(&service_name) {
+        return xembed::send_click(&service_name, 3, x, y);
+    }
+
+    item_context_menu(&tray_manager, &service_name, x, y).await
+}
+
+#[tauri::command]
+async fn tray_item_scroll(
+    service_name: String,
+    delta: i32,
+    orientation: String,
+    tray_manager: tauri::State<'_, TrayManager>,
+) -> Result<(), TrayError> {
+    let orientation = orientation.to_lowercase();
+    if orientation != "vertical" && orientation != "horizontal" {
+        return Err(TrayError::InvalidArgument(format!("Orientación de scroll inválida: {}", orientation)));
+    }
+
+    #[cfg(feature = "x11")]
+    if xembed::is_xembed_item(&service_name) {
+        let button = match (orientation.as_str(), delta < 0) {
+            ("vertical", true) => 4,
+            ("vertical", false) => 5,
+            (_, true) => 6,
+            (_, false) => 7,
+        };
+        return xembed::send_click(&service_name, button, 0, 0);
+    }
+
+    scroll_item(&tray_manager, &service_name, delta, &orientation).await
 }
 
 #[tauri::command]
 async fn get_tray_menu(
-    _service_name: String,
-) -> Result<Vec<TrayMenu>, String> {
-    // Implementation for getting menu items
-    Ok(vec![])
+    service_name: String,
+    tray_manager: tauri::State<'_, TrayManager>,
+    menu_cache: tauri::State<'_, TrayMenuCache>,
+) -> Result<Vec<TrayMenu>, TrayError> {
+    let (bus_name, menu_path) = get_item_menu(&tray_manager, &service_name).await?;
+    let connection = Connection::session().await?;
+
+    MenuParser::open_menu(&connection, &menu_cache, &service_name, &bus_name, &menu_path, 0)
+        .await
+        .map_err(|e| TrayError::DBus(format!("Error obteniendo menú de {}: {}", service_name, e)))
+}
+
+#[tauri::command]
+async fn get_tray_submenu(
+    service_name: String,
+    menu_id: i32,
+    tray_manager: tauri::State<'_, TrayManager>,
+    menu_cache: tauri::State<'_, TrayMenuCache>,
+) -> Result<Vec<TrayMenu>, TrayError> {
+    let (bus_name, menu_path) = get_item_menu(&tray_manager, &service_name).await?;
+    let connection = Connection::session().await?;
+
+    MenuParser::open_menu(&connection, &menu_cache, &service_name, &bus_name, &menu_path, menu_id)
+        .await
+        .map_err(|e| TrayError::DBus(format!("Error obteniendo submenú de {}: {}", service_name, e)))
+}
+
+#[tauri::command]
+async fn tray_menu_closed(
+    service_name: String,
+    menu_id: Option<i32>,
+    tray_manager: tauri::State<'_, TrayManager>,
+) -> Result<(), TrayError> {
+    let (bus_name, menu_path) = get_item_menu(&tray_manager, &service_name).await?;
+    let connection = Connection::session().await?;
+
+    MenuParser::close_menu(&connection, &bus_name, &menu_path, menu_id.unwrap_or(0))
+        .await
+        .map_err(|e| TrayError::DBus(format!("Error cerrando menú de {}: {}", service_name, e)))
 }
 
 #[tauri::command]
 async fn tray_menu_item_click(
-    _service_name: String,
-    _menu_id: i32,
-) -> Result<(), String> {
-    // Implementation for menu item click
-    Ok(())
+    service_name: String,
+    menu_id: i32,
+    tray_manager: tauri::State<'_, TrayManager>,
+) -> Result<(), TrayError> {
+    let (bus_name, menu_path) = get_item_menu(&tray_manager, &service_name).await?;
+    let connection = Connection::session().await?;
+
+    MenuParser::trigger_menu_item(&connection, &bus_name, &menu_path, menu_id)
+        .await
+        .map_err(|e| TrayError::DBus(format!("Error en click de menú de {}: {}", service_name, e)))
+}
+
+fn parse_window_id(window_id: &str) -> Result<u32, TrayError> {
+    window_id
+        .parse()
+        .map_err(|_| TrayError::InvalidArgument(format!("Id de ventana inválido: {}", window_id)))
+}
+
+/// Menu bar of the focused window, or `None` when it registered no menu.
+#[tauri::command]
+async fn get_active_app_menu(
+    registry: tauri::State<'_, AppMenuRegistry>,
+    menu_cache: tauri::State<'_, TrayMenuCache>,
+) -> Result<Option<AppMenu>, TrayError> {
+    let Some(window_id) = registry.read().await.active_window else {
+        return Ok(None);
+    };
+    let Ok((bus_name, menu_path)) = get_window_menu(&registry, window_id).await else {
+        return Ok(None);
+    };
+
+    let service_name = menu_key(window_id);
+    let connection = Connection::session().await?;
+    let items = MenuParser::open_menu(&connection, &menu_cache, &service_name, &bus_name, &menu_path, 0)
+        .await
+        .map_err(|e| TrayError::DBus(format!("Error obteniendo menú de la ventana {}: {}", window_id, e)))?;
+
+    Ok(Some(AppMenu {
+        window_id: window_id.to_string(),
+        service_name,
+        items,
+    }))
+}
+
+#[tauri::command]
+async fn get_app_submenu(
+    window_id: String,
+    menu_id: i32,
+    registry: tauri::State<'_, AppMenuRegistry>,
+    menu_cache: tauri::State<'_, TrayMenuCache>,
+) -> Result<Vec<TrayMenu>, TrayError> {
+    let window_id = parse_window_id(&window_id)?;
+    let (bus_name, menu_path) = get_window_menu(&registry, window_id).await?;
+    let connection = Connection::session().await?;
+
+    MenuParser::open_menu(&connection, &menu_cache, &menu_key(window_id), &bus_name, &menu_path, menu_id)
+        .await
+        .map_err(|e| TrayError::DBus(format!("Error obteniendo submenú de la ventana {}: {}", window_id, e)))
 }
 
+#[tauri::command]
+async fn app_menu_closed(
+    window_id: String,
+    menu_id: Option<i32>,
+    registry: tauri::State<'_, AppMenuRegistry>,
+) -> Result<(), TrayError> {
+    let window_id = parse_window_id(&window_id)?;
+    let (bus_name, menu_path) = get_window_menu(&registry, window_id).await?;
+    let connection = Connection::session().await?;
+
+    MenuParser::close_menu(&connection, &bus_name, &menu_path, menu_id.unwrap_or(0))
+        .await
+        .map_err(|e| TrayError::DBus(format!("Error cerrando menú de la ventana {}: {}", window_id, e)))
+}
+
+#[tauri::command]
+async fn app_menu_item_click(
+    window_id: String,
+    menu_id: i32,
+    registry: tauri::State<'_, AppMenuRegistry>,
+) -> Result<(), TrayError> {
+    let window_id = parse_window_id(&window_id)?;
+    let (bus_name, menu_path) = get_window_menu(&registry, window_id).await?;
+    let connection = Connection::session().await?;
+
+    MenuParser::trigger_menu_item(&connection, &bus_name, &menu_path, menu_id)
+        .await
+        .map_err(|e| TrayError::DBus(format!("Error en click de menú de la ventana {}: {}", window_id, e)))
+}
+
+/// Lists the windows with their icons rendered at the panel's height in
+/// physical pixels, so they stay sharp at any scale.
+fn list_windows(
+    app_handle: &tauri::AppHandle,
+    state: &AppState,
+    index: &DesktopIndex,
+) -> Result<Vec<WindowInfo>, WindowManagerError> {
+    let icon_size = app_handle
+        .get_webview_window("main")
+        .and_then(|window| window.inner_size().ok())
+        .map(|size| size.height);
+
+    let mut window_manager = state
+        .window_manager
+        .lock()
+        .map_err(|e| WindowManagerError::Backend(e.to_string()))?;
+    if let Some(icon_size) = icon_size {
+        window_manager.set_icon_size(icon_size);
+    }
+    window_manager.get_window_list(index)
+}
 
 // Comandos de la API
+/// Pinned applications with their windows, and every other window, listed
+/// once so both always come from the same snapshot.
+#[tauri::command]
+async fn get_windows(
+    sort: Option<SortMode>,
+    group: Option<bool>,
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<Taskbar, WindowManagerError> {
+    let config = config::load_config(&app_handle).await;
+    let sort = sort.unwrap_or_else(|| config::section(&config, "/panel/windows/sort"));
+    let group = group.unwrap_or_else(|| config::section(&config, "/panel/windows/group"));
+
+    let index = DesktopIndex::current();
+    let windows = list_windows(&app_handle, &state, &index)?;
+
+    // Windows of pinned applications are shown by their launcher
+    let (launchers, mut windows) = launcher::merge_pinned(&launcher::pinned(&config), &index, windows);
+    sort_windows(&mut windows, sort);
+    let windows = if group {
+        WindowList::Groups(group_windows(windows))
+    } else {
+        WindowList::Windows(windows)
+    };
+
+    Ok(Taskbar { launchers, windows })
+}
+
 #[tauri::command]
-async fn get_windows(state: tauri::State<'_, AppState>) -> Result<Vec<WindowInfo>, String> {
+async fn toggle_window(window_id: String, state: tauri::State<'_, AppState>) -> Result<(), WindowManagerError> {
     state
         .window_manager
         .lock()
-        .map_err(|e| e.to_string())?
-        .get_window_list()
-        .map_err(|e| e.to_string())
+        .map_err(|e| WindowManagerError::Backend(e.to_string()))?
+        .toggle_window(&window_id)
 }
 
+/// Window actions the current backend supports, for the context menu.
 #[tauri::command]
-async fn toggle_window(window_id: String, state: tauri::State<'_, AppState>) -> Result<(), String> {
+async fn get_window_capabilities(state: tauri::State<'_, AppState>) -> Result<WindowCapabilities, WindowManagerError> {
+    Ok(state
+        .window_manager
+        .lock()
+        .map_err(|e| WindowManagerError::Backend(e.to_string()))?
+        .capabilities())
+}
+
+#[tauri::command]
+async fn window_action(
+    window_id: String,
+    action: WindowAction,
+    state: tauri::State<'_, AppState>,
+) -> Result<(), WindowManagerError> {
     state
         .window_manager
         .lock()
-        .map_err(|e| e.to_string())?
-        .toggle_window(&window_id)
-        .map_err(|e| e.to_string())
+        .map_err(|e| WindowManagerError::Backend(e.to_string()))?
+        .perform(&window_id, action)
+}
+
+#[tauri::command]
+async fn set_app_pinned(
+    desktop_id: String,
+    pinned: bool,
+    position: Option<usize>,
+    app_handle: tauri::AppHandle,
+) -> Result<(), LauncherError> {
+    launcher::set_pinned(&app_handle, &desktop_id, pinned, position)
+        .await
+        .map_err(|e| LauncherError::Config(format!("Error guardando las aplicaciones fijadas: {}", e)))?;
+
+    // Windows move between their launcher and the window list
+    if let Err(e) = app_handle.emit("window-update", ()) {
+        eprintln!("[Launcher] Error emitiendo evento window-update: {}", e);
+    }
+    Ok(())
+}
+
+#[tauri::command]
+async fn launch_app(desktop_id: String, files: Option<Vec<String>>) -> Result<(), LauncherError> {
+    launcher::launch_app(&desktop_id, &files.unwrap_or_default())
+}
+
+#[tauri::command]
+async fn toggle_group(window_ids: Vec<String>, state: tauri::State<'_, AppState>) -> Result<(), WindowManagerError> {
+    state
+        .window_manager
+        .lock()
+        .map_err(|e| WindowManagerError::Backend(e.to_string()))?
+        .toggle_group(&window_ids)
 }
 
 // Configuración del monitoreo de eventos
 fn setup_event_monitoring(
     window_manager: Arc<Mutex<WindowManager>>,
+    app_menu_registry: AppMenuRegistry,
     app_handle: tauri::AppHandle,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let (tx, rx) = channel();
@@ -111,8 +449,24 @@ fn setup_event_monitoring(
     }
 
     std::thread::spawn(move || {
+        // The global menu follows the focused window
+        let update_active_window = || {
+            let active_window = window_manager
+                .lock()
+                .ok()
+                .and_then(|wm| wm.get_active_window().ok().flatten())
+                .and_then(|id| id.parse::<u32>().ok());
+            tauri::async_runtime::block_on(app_menu::set_active_window(
+                &app_menu_registry,
+                &app_handle,
+                active_window,
+            ));
+        };
+
+        update_active_window();
         for _ in rx {
             let _ = app_handle.emit("window-update", ());
+            update_active_window();
         }
     });
 
@@ -130,10 +484,17 @@ pub fn run() {
     };
 
     let tray_manager = create_tray_manager();
+    let tray_menu_cache = create_tray_menu_cache();
+    let app_menu_registry = create_app_menu_registry();
+    let sni_watcher = create_sni_watcher_handle();
 
     tauri::Builder::default()
         .manage(app_state)
-        .manage(tray_manager)
+        .manage(tray_manager.clone())
+        .manage(tray_menu_cache.clone())
+        .manage(app_menu_registry.clone())
+        .manage(sni_watcher.clone())
+        .manage(config::create_config_cache())
         .plugin(tauri_plugin_positioner::init())
         .plugin(tauri_plugin_shell::init())
         .plugin(tauri_plugin_config_manager::init())
@@ -149,20 +510,61 @@ pub fn run() {
                 .expect("main window not found");
 
             let _ = &window.move_window(Position::BottomLeft);
-            setup_event_monitoring(window_manager.clone(), app.handle().clone())?;
+            config::watch_config(app.handle());
+            setup_event_monitoring(window_manager.clone(), app_menu_registry.clone(), app.handle().clone())?;
+
+            let app_handle = app.handle().clone();
+            let watcher = sni_watcher.clone();
+            let manager = tray_manager.clone();
+            let menu_cache = tray_menu_cache.clone();
+            tauri::async_runtime::spawn(async move {
+                start_sni_watcher(&watcher, manager, menu_cache, app_handle).await;
+            });
+
+            let app_handle = app.handle().clone();
+            let registry = app_menu_registry.clone();
+            let menu_cache = tray_menu_cache.clone();
+            tauri::async_runtime::spawn(async move {
+                if let Err(e) = registrar::start_registrar(registry, menu_cache, app_handle).await {
+                    eprintln!("[AppMenu] Error iniciando AppMenu.Registrar: {}", e);
+                }
+            });
 
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
             get_windows,
             toggle_window,
+            toggle_group,
+            get_window_capabilities,
+            window_action,
+            set_app_pinned,
+            launch_app,
             init_sni_watcher,
+            tray_status,
+            init_xembed_tray,
             get_tray_items,
+            set_tray_item_policy,
+            set_panel_status,
             tray_item_activate,
             tray_item_secondary_activate,
+            tray_item_context_menu,
+            tray_item_scroll,
             get_tray_menu,
-            tray_menu_item_click
+            get_tray_submenu,
+            tray_menu_closed,
+            tray_menu_item_click,
+            get_active_app_menu,
+            get_app_submenu,
+            app_menu_closed,
+            app_menu_item_click
         ])
-        .run(generate_context!())
-        .expect("error while running tauri application");
+        .build(generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            if let tauri::RunEvent::Exit = event {
+                let sni_watcher = app.state::<SniWatcherHandle>();
+                tauri::async_runtime::block_on(stop_sni_watcher(&sni_watcher));
+            }
+        });
 }
@@ -0,0 +1,60 @@
+use serde::Serialize;
+use std::fmt;
+
+/// Tray failure, sent to the frontend as `{ kind, message }`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", content = "message", rename_all = "kebab-case")]
+pub enum TrayError {
+    /// The session bus (or the X display for XEmbed) is unreachable
+    ConnectionLost(String),
+    /// The tray protocol could not be served, e.g. no compositing for XEmbed
+    ProtocolUnavailable(String),
+    ItemNotFound(String),
+    /// The item or window exports no dbusmenu
+    NoMenu(String),
+    InvalidArgument(String),
+    /// A D-Bus call to an item, menu or watcher failed
+    DBus(String),
+    /// Reading or writing the tray settings failed
+    Config(String),
+}
+
+impl fmt::Display for TrayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ConnectionLost(message)
+            | Self::ProtocolUnavailable(message)
+            | Self::ItemNotFound(message)
+            | Self::NoMenu(message)
+            | Self::InvalidArgument(message)
+            | Self::DBus(message)
+            | Self::Config(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for TrayError {}
+
+impl From<zbus::Error> for TrayError {
+    fn from(error: zbus::Error) -> Self {
+        match error {
+            zbus::Error::InputOutput(_) | zbus::Error::Address(_) | zbus::Error::Handshake(_) => {
+                Self::ConnectionLost(format!("Conexión con el bus de sesión perdida: {}", error))
+            }
+            error => Self::DBus(error.to_string()),
+        }
+    }
+}
+
+#[cfg(feature = "x11")]
+impl From<x11rb::errors::ConnectionError> for TrayError {
+    fn from(error: x11rb::errors::ConnectionError) -> Self {
+        Self::ConnectionLost(format!("Conexión con X11 perdida: {}", error))
+    }
+}
+
+impl From<zbus::fdo::Error> for TrayError {
+    fn from(error: zbus::fdo::Error) -> Self {
+        Self::DBus(error.to_string())
+    }
+}
@@ -1,49 +1,422 @@
-use super::TrayMenu;
-use zbus::{Connection, Proxy, zvariant::Value};
+use super::{TrayMenu, TrayMenuCache, emit_tray_menu_update};
+use base64::{Engine as _, engine::general_purpose};
+use futures_util::stream::StreamExt;
+use std::collections::HashMap;
+use tauri::AppHandle;
+use zbus::{Connection, Proxy, zvariant::{OwnedValue, Value}};
+
+const DBUSMENU_INTERFACE: &str = "com.canonical.dbusmenu";
+
+/// A `(ia{sv}av)` node as returned by `GetLayout`: id, properties and children.
+type MenuLayout = (i32, HashMap<String, OwnedValue>, Vec<OwnedValue>);
+
+/// Body of the `ItemsPropertiesUpdated` signal: updated and removed properties.
+type PropertiesUpdate = (
+    Vec<(i32, HashMap<String, OwnedValue>)>,
+    Vec<(i32, Vec<String>)>,
+);
+
+/// Raw dbusmenu node, kept so property updates can be patched in place.
+#[derive(Debug)]
+pub struct MenuNode {
+    pub id: i32,
+    pub properties: HashMap<String, OwnedValue>,
+    pub children: Vec<MenuNode>,
+}
+
+impl MenuNode {
+    fn from_layout(layout: MenuLayout) -> Result<Self, Box<dyn std::error::Error>> {
+        let (id, properties, children) = layout;
+
+        let children = children
+            .into_iter()
+            .map(|child| {
+                let child: MenuLayout = Value::from(child).downcast()?;
+                Self::from_layout(child)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self {
+            id,
+            properties,
+            children,
+        })
+    }
+
+    fn find(&self, id: i32) -> Option<&MenuNode> {
+        if self.id == id {
+            return Some(self);
+        }
+        self.children.iter().find_map(|child| child.find(id))
+    }
+
+    fn find_mut(&mut self, id: i32) -> Option<&mut MenuNode> {
+        if self.id == id {
+            return Some(self);
+        }
+        self.children.iter_mut().find_map(|child| child.find_mut(id))
+    }
+
+    pub fn to_tray_menu(&self) -> TrayMenu {
+        let mut item = MenuParser::parse_menu_item(self.id, &self.properties);
+
+        if !self.children.is_empty() || item.menu_type == "submenu" {
+            item.menu_type = "submenu".to_string();
+            item.children = Some(self.children.iter().map(Self::to_tray_menu).collect());
+        }
+
+        item
+    }
+}
 
 pub struct MenuParser;
 
 impl MenuParser {
-    pub async fn get_menu_items(
+    /// Prepares a menu (root `0` or a submenu id) for display, following the
+    /// dbusmenu protocol: `AboutToShow` on the menu and `AboutToShowGroup` on
+    /// its submenus, refetching whatever the application reports as changed,
+    /// then an `opened` event. Returns the children of the menu.
+    pub async fn open_menu(
         connection: &Connection,
+        menu_cache: &TrayMenuCache,
+        tray_id: &str,
         service_name: &str,
         menu_path: &str,
+        menu_id: i32,
     ) -> Result<Vec<TrayMenu>, Box<dyn std::error::Error>> {
         let proxy = Proxy::new(
             connection,
             service_name,
             menu_path,
-            "com.canonical.dbusmenu",
+            DBUSMENU_INTERFACE,
+        ).await?;
+
+        // Not every application implements AboutToShow; treat failures as "no update"
+        let needs_update = proxy
+            .call::<_, _, bool>("AboutToShow", &(menu_id,))
+            .await
+            .unwrap_or(false);
+
+        let is_cached = menu_cache
+            .read()
+            .await
+            .get(tray_id)
+            .and_then(|state| state.root.as_ref())
+            .is_some_and(|root| root.find(menu_id).is_some());
+
+        if !is_cached {
+            let (revision, root) = Self::get_layout(connection, service_name, menu_path, 0).await?;
+            Self::replace_node(menu_cache, tray_id, revision, root).await;
+        } else if needs_update {
+            let (revision, node) = Self::get_layout(connection, service_name, menu_path, menu_id).await?;
+            Self::replace_node(menu_cache, tray_id, revision, node).await;
+        }
+
+        // Lazily populated submenus (Electron, Qt) only fill in their children
+        // after being told they are about to be shown
+        let submenu_ids = Self::get_submenu_ids(menu_cache, tray_id, menu_id).await;
+        if !submenu_ids.is_empty() {
+            for id in Self::about_to_show_group(&proxy, &submenu_ids).await {
+                let (revision, node) = Self::get_layout(connection, service_name, menu_path, id).await?;
+                Self::replace_node(menu_cache, tray_id, revision, node).await;
+            }
+        }
+
+        Self::send_event(&proxy, menu_id, "opened").await?;
+
+        let cache = menu_cache.read().await;
+        let items = cache
+            .get(tray_id)
+            .and_then(|state| state.root.as_ref())
+            .and_then(|root| root.find(menu_id))
+            .map(|node| node.children.iter().map(MenuNode::to_tray_menu).collect())
+            .unwrap_or_default();
+
+        Ok(items)
+    }
+
+    /// Sends the `closed` event for a menu previously shown with `open_menu`.
+    pub async fn close_menu(
+        connection: &Connection,
+        service_name: &str,
+        menu_path: &str,
+        menu_id: i32,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let proxy = Proxy::new(
+            connection,
+            service_name,
+            menu_path,
+            DBUSMENU_INTERFACE,
+        ).await?;
+
+        Self::send_event(&proxy, menu_id, "closed").await
+    }
+
+    async fn get_submenu_ids(menu_cache: &TrayMenuCache, tray_id: &str, menu_id: i32) -> Vec<i32> {
+        let cache = menu_cache.read().await;
+        cache
+            .get(tray_id)
+            .and_then(|state| state.root.as_ref())
+            .and_then(|root| root.find(menu_id))
+            .map(|node| {
+                node.children
+                    .iter()
+                    .filter(|child| {
+                        !child.children.is_empty()
+                            || Self::get_str(&child.properties, "children-display") == Some("submenu")
+                    })
+                    .map(|child| child.id)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Calls `AboutToShowGroup`, falling back to one `AboutToShow` per id for
+    /// older implementations. Returns the ids whose layout needs refetching.
+    async fn about_to_show_group(proxy: &Proxy<'_>, ids: &[i32]) -> Vec<i32> {
+        if let Ok((updates_needed, _id_errors)) = proxy
+            .call::<_, _, (Vec<i32>, Vec<i32>)>("AboutToShowGroup", &(ids,))
+            .await
+        {
+            return updates_needed;
+        }
+
+        let mut updates_needed = Vec::new();
+        for &id in ids {
+            if let Ok(true) = proxy.call::<_, _, bool>("AboutToShow", &(id,)).await {
+                updates_needed.push(id);
+            }
+        }
+
+        updates_needed
+    }
+
+    async fn send_event(
+        proxy: &Proxy<'_>,
+        menu_id: i32,
+        event_id: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let _: () = proxy
+            .call("Event", &(menu_id, event_id, Value::from(""), 0_u32))
+            .await?;
+
+        Ok(())
+    }
+
+    async fn get_layout(
+        connection: &Connection,
+        service_name: &str,
+        menu_path: &str,
+        parent_id: i32,
+    ) -> Result<(u32, MenuNode), Box<dyn std::error::Error>> {
+        let proxy = Proxy::new(
+            connection,
+            service_name,
+            menu_path,
+            DBUSMENU_INTERFACE,
         ).await?;
 
-        // Simplified layout call without specific lifetime requirements
-        let _layout: zbus::zvariant::OwnedValue = proxy.call("GetLayout", &(0_u32, -1_i32)).await?;
-
-        // For now, return empty menu as a placeholder
-        // Real implementation would parse the layout structure
-        Ok(vec![])
-    }
-
-    fn parse_menu_item(_item: &Value) -> Result<TrayMenu, Box<dyn std::error::Error>> {
-        // Parse DBus menu item structure
-        // This is a simplified version - real implementation would be more complex
-        
-        let id = 0; // Extract from item
-        let label = "Menu Item".to_string(); // Extract from item
-        let enabled = true; // Extract from item
-        let visible = true; // Extract from item
-        let menu_type = "standard".to_string();
-        
-        Ok(TrayMenu {
+        // Unlimited depth, all properties
+        let (revision, layout): (u32, MenuLayout) = proxy
+            .call("GetLayout", &(parent_id, -1_i32, Vec::<&str>::new()))
+            .await?;
+
+        Ok((revision, MenuNode::from_layout(layout)?))
+    }
+
+    /// Follows `LayoutUpdated` and `ItemsPropertiesUpdated` on a tray item's
+    /// menu, keeping its entry in the menu cache up to date.
+    pub async fn watch_menu(
+        connection: Connection,
+        menu_cache: TrayMenuCache,
+        app_handle: AppHandle,
+        tray_id: String,
+        service_name: String,
+        menu_path: String,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let proxy = Proxy::new(
+            &connection,
+            service_name.clone(),
+            menu_path.clone(),
+            DBUSMENU_INTERFACE,
+        ).await?;
+
+        let mut layout_stream = proxy.receive_signal("LayoutUpdated").await?;
+        let mut properties_stream = proxy.receive_signal("ItemsPropertiesUpdated").await?;
+
+        let task = tokio::spawn({
+            let menu_cache = menu_cache.clone();
+            let tray_id = tray_id.clone();
+
+            async move {
+                loop {
+                    tokio::select! {
+                        Some(message) = layout_stream.next() => {
+                            let Ok((revision, parent_id)) = message.body().deserialize::<(u32, i32)>() else {
+                                continue;
+                            };
+
+                            let layout = Self::get_layout(&connection, &service_name, &menu_path, parent_id)
+                                .await
+                                .map_err(|e| e.to_string());
+
+                            match layout {
+                                Ok((_, node)) => {
+                                    Self::replace_node(&menu_cache, &tray_id, revision, node).await;
+                                    emit_tray_menu_update(&app_handle, &tray_id, revision).await;
+                                }
+                                Err(e) => {
+                                    eprintln!("[Tray] Error actualizando menú de {}: {}", tray_id, e);
+                                }
+                            }
+                        }
+                        Some(message) = properties_stream.next() => {
+                            let Ok((updated, removed)) = message.body().deserialize::<PropertiesUpdate>() else {
+                                continue;
+                            };
+
+                            if let Some(revision) = Self::patch_properties(&menu_cache, &tray_id, updated, removed).await {
+                                emit_tray_menu_update(&app_handle, &tray_id, revision).await;
+                            }
+                        }
+                        else => break,
+                    }
+                }
+            }
+        });
+
+        let mut cache = menu_cache.write().await;
+        let state = cache.entry(tray_id.clone()).or_default();
+        if let Some(previous) = state.watcher.replace(task.abort_handle()) {
+            previous.abort();
+        }
+
+        Ok(())
+    }
+
+    async fn replace_node(menu_cache: &TrayMenuCache, tray_id: &str, revision: u32, node: MenuNode) {
+        let mut cache = menu_cache.write().await;
+        let state = cache.entry(tray_id.to_string()).or_default();
+        state.revision = revision;
+
+        match state.root.as_mut().and_then(|root| root.find_mut(node.id)) {
+            Some(existing) => *existing = node,
+            None if node.id == 0 => state.root = Some(node),
+            // A subtree we never fetched; the next GetLayout will pick it up
+            None => {}
+        }
+    }
+
+    /// Applies an `ItemsPropertiesUpdated` patch, returning the cached revision
+    /// if the item has a cached menu.
+    async fn patch_properties(
+        menu_cache: &TrayMenuCache,
+        tray_id: &str,
+        updated: Vec<(i32, HashMap<String, OwnedValue>)>,
+        removed: Vec<(i32, Vec<String>)>,
+    ) -> Option<u32> {
+        let mut cache = menu_cache.write().await;
+        let state = cache.get_mut(tray_id)?;
+        let root = state.root.as_mut()?;
+
+        for (id, properties) in updated {
+            if let Some(node) = root.find_mut(id) {
+                node.properties.extend(properties);
+            }
+        }
+
+        for (id, names) in removed {
+            if let Some(node) = root.find_mut(id) {
+                for name in names {
+                    node.properties.remove(&name);
+                }
+            }
+        }
+
+        Some(state.revision)
+    }
+
+    fn parse_menu_item(id: i32, properties: &HashMap<String, OwnedValue>) -> TrayMenu {
+        let label = Self::get_str(properties, "label")
+            .map(Self::strip_mnemonic)
+            .unwrap_or_default();
+        let enabled = Self::get_bool(properties, "enabled").unwrap_or(true);
+        let visible = Self::get_bool(properties, "visible").unwrap_or(true);
+
+        let menu_type = if Self::get_str(properties, "type") == Some("separator") {
+            "separator"
+        } else if Self::get_str(properties, "children-display") == Some("submenu") {
+            "submenu"
+        } else {
+            "standard"
+        }
+        .to_string();
+
+        // toggle-state: 0 = off, 1 = on, anything else = indeterminate
+        let checked = match Self::get_str(properties, "toggle-type") {
+            Some("checkmark") | Some("radio") => {
+                Some(Self::get_i32(properties, "toggle-state") == Some(1))
+            }
+            _ => None,
+        };
+
+        let icon = Self::get_bytes(properties, "icon-data")
+            .filter(|data| !data.is_empty())
+            .map(|data| format!("data:image/png;base64,{}", general_purpose::STANDARD.encode(data)))
+            .or_else(|| {
+                Self::get_str(properties, "icon-name")
+                    .filter(|name| !name.is_empty())
+                    .map(str::to_string)
+            });
+
+        TrayMenu {
             id,
             label,
             enabled,
             visible,
             menu_type,
-            checked: None,
-            icon: None,
+            checked,
+            icon,
             children: None,
-        })
+        }
+    }
+
+    /// Removes GTK/Qt mnemonic markers: `_File` becomes `File`, `__` becomes `_`.
+    fn strip_mnemonic(label: &str) -> String {
+        let mut result = String::with_capacity(label.len());
+        let mut chars = label.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            if c == '_' {
+                if chars.peek() == Some(&'_') {
+                    result.push('_');
+                    chars.next();
+                }
+            } else {
+                result.push(c);
+            }
+        }
+
+        result
+    }
+
+    fn get_str<'a>(properties: &'a HashMap<String, OwnedValue>, key: &str) -> Option<&'a str> {
+        properties.get(key).and_then(|v| v.downcast_ref::<&str>().ok())
+    }
+
+    fn get_bool(properties: &HashMap<String, OwnedValue>, key: &str) -> Option<bool> {
+        properties.get(key).and_then(|v| v.downcast_ref::<bool>().ok())
+    }
+
+    fn get_i32(properties: &HashMap<String, OwnedValue>, key: &str) -> Option<i32> {
+        properties.get(key).and_then(|v| v.downcast_ref::<i32>().ok())
+    }
+
+    fn get_bytes(properties: &HashMap<String, OwnedValue>, key: &str) -> Option<Vec<u8>> {
+        properties
+            .get(key)
+            .and_then(|v| v.try_clone().ok())
+            .and_then(|v| Vec::<u8>::try_from(v).ok())
     }
 
     pub async fn trigger_menu_item(
@@ -56,11 +429,135 @@ impl MenuParser {
             connection,
             service_name,
             menu_path,
-            "com.canonical.dbusmenu",
+            DBUSMENU_INTERFACE,
         ).await?;
 
-        let _: () = proxy.call("Event", &(menu_id, "clicked", "", 0_u32)).await?;
-        
-        Ok(())
+        Self::send_event(&proxy, menu_id, "clicked").await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zbus::zvariant::Structure;
+
+    fn properties(entries: Vec<(&str, Value<'static>)>) -> HashMap<String, OwnedValue> {
+        entries
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), OwnedValue::try_from(value).unwrap()))
+            .collect()
+    }
+
+    /// A `(ia{sv}av)` child as it arrives inside a parent's `av`.
+    fn child(id: i32, entries: Vec<(&str, Value<'static>)>, children: Vec<OwnedValue>) -> OwnedValue {
+        OwnedValue::try_from(Structure::from((id, properties(entries), children))).unwrap()
+    }
+
+    fn item(entries: Vec<(&str, Value<'static>)>) -> TrayMenu {
+        MenuParser::parse_menu_item(1, &properties(entries))
+    }
+
+    #[test]
+    fn strips_mnemonics() {
+        assert_eq!(MenuParser::strip_mnemonic("_Archivo"), "Archivo");
+        assert_eq!(MenuParser::strip_mnemonic("Guardar _como"), "Guardar como");
+        assert_eq!(MenuParser::strip_mnemonic("snake__case"), "snake_case");
+        assert_eq!(MenuParser::strip_mnemonic("a___b"), "a_b");
+        assert_eq!(MenuParser::strip_mnemonic("Sin marcas"), "Sin marcas");
+    }
+
+    #[test]
+    fn defaults_to_enabled_and_visible() {
+        let parsed = item(vec![("label", Value::from("_Salir"))]);
+        assert_eq!(parsed.label, "Salir");
+        assert!(parsed.enabled);
+        assert!(parsed.visible);
+        assert_eq!(parsed.menu_type, "standard");
+        assert_eq!(parsed.checked, None);
+        assert_eq!(parsed.icon, None);
+
+        let parsed = item(vec![("enabled", Value::from(false)), ("visible", Value::from(false))]);
+        assert!(!parsed.enabled);
+        assert!(!parsed.visible);
+    }
+
+    #[test]
+    fn parses_separators() {
+        let parsed = item(vec![("type", Value::from("separator"))]);
+        assert_eq!(parsed.menu_type, "separator");
+    }
+
+    #[test]
+    fn maps_toggle_state_to_checked() {
+        let toggle = |toggle_type: &'static str, state: i32| {
+            item(vec![("toggle-type", Value::from(toggle_type)), ("toggle-state", Value::from(state))]).checked
+        };
+
+        assert_eq!(toggle("checkmark", 1), Some(true));
+        assert_eq!(toggle("checkmark", 0), Some(false));
+        assert_eq!(toggle("radio", 1), Some(true));
+        // Indeterminate
+        assert_eq!(toggle("checkmark", -1), Some(false));
+        assert_eq!(toggle("", 1), None);
+    }
+
+    #[test]
+    fn prefers_icon_data_over_icon_name() {
+        let parsed = item(vec![
+            ("icon-name", Value::from("document-open")),
+            ("icon-data", Value::from(vec![1_u8, 2, 3])),
+        ]);
+        assert_eq!(parsed.icon.as_deref(), Some("data:image/png;base64,AQID"));
+
+        let parsed = item(vec![
+            ("icon-name", Value::from("document-open")),
+            ("icon-data", Value::from(Vec::<u8>::new())),
+        ]);
+        assert_eq!(parsed.icon.as_deref(), Some("document-open"));
+
+        assert_eq!(item(vec![("icon-name", Value::from(""))]).icon, None);
+    }
+
+    #[test]
+    fn builds_the_menu_tree_from_a_layout() {
+        let layout: MenuLayout = (
+            0,
+            properties(vec![("children-display", Value::from("submenu"))]),
+            vec![
+                child(
+                    1,
+                    vec![("label", Value::from("_Archivo"))],
+                    vec![
+                        child(2, vec![("label", Value::from("_Abrir"))], vec![]),
+                        child(3, vec![("type", Value::from("separator"))], vec![]),
+                    ],
+                ),
+                // Filled in lazily after AboutToShow
+                child(4, vec![("label", Value::from("Recientes")), ("children-display", Value::from("submenu"))], vec![]),
+                child(5, vec![("label", Value::from("_Salir"))], vec![]),
+            ],
+        );
+
+        let root = MenuNode::from_layout(layout).unwrap();
+        assert_eq!(root.find(3).map(|node| node.id), Some(3));
+        assert!(root.find(6).is_none());
+
+        let menu = root.to_tray_menu();
+        assert_eq!(menu.menu_type, "submenu");
+        let items = menu.children.unwrap();
+        assert_eq!(items.iter().map(|item| item.id).collect::<Vec<_>>(), [1, 4, 5]);
+
+        assert_eq!(items[0].label, "Archivo");
+        assert_eq!(items[0].menu_type, "submenu");
+        let file = items[0].children.as_ref().unwrap();
+        assert_eq!(file[0].label, "Abrir");
+        assert!(file[0].children.is_none());
+        assert_eq!(file[1].menu_type, "separator");
+
+        assert_eq!(items[1].menu_type, "submenu");
+        assert!(items[1].children.as_ref().is_some_and(|children| children.is_empty()));
+
+        assert_eq!(items[2].label, "Salir");
+        assert_eq!(items[2].menu_type, "standard");
     }
 }
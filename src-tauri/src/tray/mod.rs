@@ -1,24 +1,83 @@
+pub mod sni_host;
 pub mod sni_watcher;
+pub mod sni_watcher_interface;
+pub mod tooltip;
 pub mod sni_item;
+pub mod error;
 pub mod menu_parser;
+pub mod panel_item;
+pub mod pixmap;
+pub mod policy;
+#[cfg(feature = "x11")]
+pub mod xembed;
 
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use tokio::sync::RwLock;
+use tokio::task::AbortHandle;
 use serde::{Deserialize, Serialize};
-use tauri::{AppHandle, Emitter};
+use tauri::{AppHandle, Emitter, Manager};
+use zbus::{proxy::CacheProperties, Connection};
+use sni_item::{SniItemProxy, SniNamespace};
+use menu_parser::MenuNode;
+
+pub use error::TrayError;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TrayItem {
     pub id: String,
     pub service_name: String,
     pub icon_name: Option<String>,
+    /// Base64 PNG: the attention icon while `NeedsAttention`, with the
+    /// overlay icon composited as a badge
     pub icon_data: Option<String>,
+    /// Frames of the `AttentionMovieName` animation, if any
+    pub attention_frames: Option<Vec<TrayIconFrame>>,
     pub title: Option<String>,
-    pub tooltip: Option<String>,
+    pub tooltip: Option<TrayTooltip>,
     pub status: TrayStatus,
     pub category: TrayCategory,
     pub menu_path: Option<String>,
+    /// Interface names the item implements
+    #[serde(skip)]
+    pub namespace: SniNamespace,
+    /// Well-known bus name the item registered with; the key uses its owner
+    #[serde(skip)]
+    pub registered_name: Option<String>,
+    /// Task following the item's NewIcon/NewStatus/... signals
+    #[serde(skip)]
+    pub signal_watcher: Option<AbortHandle>,
+    /// Position in order of appearance, from `next_sequence`
+    #[serde(skip)]
+    pub sequence: u64,
+}
+
+static NEXT_SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+/// Returns a number greater than any handed out before, to keep items in the
+/// order they appeared.
+pub fn next_sequence() -> u64 {
+    NEXT_SEQUENCE.fetch_add(1, Ordering::Relaxed)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrayIconFrame {
+    /// Base64 PNG
+    pub data: String,
+    pub delay_ms: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrayTooltip {
+    /// PNG data URL from the tooltip pixmap, or a themed icon name
+    pub icon: Option<String>,
+    pub title: String,
+    /// Body as sent by the item, possibly containing markup
+    pub body: String,
+    pub body_is_markup: bool,
+    /// Body with markup stripped and entities decoded
+    pub body_text: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -49,14 +108,282 @@ pub struct TrayMenu {
     pub children: Option<Vec<TrayMenu>>,
 }
 
+#[derive(Debug, Clone, Serialize)]
+pub struct TrayMenuUpdate {
+    pub service_name: String,
+    pub revision: u32,
+}
+
+/// Cached dbusmenu tree of a tray item and the task following its signals.
+#[derive(Debug, Default)]
+pub struct TrayMenuState {
+    pub revision: u32,
+    pub root: Option<MenuNode>,
+    pub watcher: Option<AbortHandle>,
+}
+
 pub type TrayManager = Arc<RwLock<HashMap<String, TrayItem>>>;
+pub type TrayMenuCache = Arc<RwLock<HashMap<String, TrayMenuState>>>;
 
 pub fn create_tray_manager() -> TrayManager {
     Arc::new(RwLock::new(HashMap::new()))
 }
 
+pub fn create_tray_menu_cache() -> TrayMenuCache {
+    Arc::new(RwLock::new(HashMap::new()))
+}
+
+/// Splits an SNI service string (`bus_name` or `bus_name/object/path`) into
+/// its bus name and object path.
+pub fn split_service_name(service_name: &str) -> (&str, String) {
+    match service_name.split_once('/') {
+        Some((bus_name, path)) => (bus_name, format!("/{}", path)),
+        None => (service_name, "/StatusNotifierItem".to_string()),
+    }
+}
+
+/// Builds a proxy to a registered tray item so its methods can be invoked.
+pub async fn get_item_proxy(
+    tray_manager: &TrayManager,
+    service_name: &str,
+) -> Result<SniItemProxy<'static>, TrayError> {
+    let namespace = tray_manager
+        .read()
+        .await
+        .get(service_name)
+        .map(|item| item.namespace)
+        .ok_or_else(|| TrayError::ItemNotFound(format!("Tray item {} not found", service_name)))?;
+
+    let (bus_name, object_path) = split_service_name(service_name);
+    let connection = Connection::session().await?;
+
+    let proxy = SniItemProxy::builder(&connection)
+        .destination(bus_name.to_string())?
+        .path(object_path)?
+        .interface(namespace.item_interface())?
+        .cache_properties(CacheProperties::No)
+        .build()
+        .await?;
+
+    Ok(proxy)
+}
+
+/// Calls `Activate` on a registered item.
+pub async fn activate_item(tray_manager: &TrayManager, service_name: &str, x: i32, y: i32) -> Result<(), TrayError> {
+    get_item_proxy(tray_manager, service_name)
+        .await?
+        .activate(x, y)
+        .await
+        .map_err(|e| TrayError::DBus(format!("Error activando {}: {}", service_name, e)))
+}
+
+pub async fn secondary_activate_item(
+    tray_manager: &TrayManager,
+    service_name: &str,
+    x: i32,
+    y: i32,
+) -> Result<(), TrayError> {
+    get_item_proxy(tray_manager, service_name)
+        .await?
+        .secondary_activate(x, y)
+        .await
+        .map_err(|e| TrayError::DBus(format!("Error en activación secundaria de {}: {}", service_name, e)))
+}
+
+pub async fn item_context_menu(tray_manager: &TrayManager, service_name: &str, x: i32, y: i32) -> Result<(), TrayError> {
+    get_item_proxy(tray_manager, service_name)
+        .await?
+        .context_menu(x, y)
+        .await
+        .map_err(|e| TrayError::DBus(format!("Error abriendo menú contextual de {}: {}", service_name, e)))
+}
+
+/// Calls `Scroll` with an already validated `vertical` or `horizontal`.
+pub async fn scroll_item(
+    tray_manager: &TrayManager,
+    service_name: &str,
+    delta: i32,
+    orientation: &str,
+) -> Result<(), TrayError> {
+    get_item_proxy(tray_manager, service_name)
+        .await?
+        .scroll(delta, orientation)
+        .await
+        .map_err(|e| TrayError::DBus(format!("Error en scroll de {}: {}", service_name, e)))
+}
+
+/// Returns the bus name and dbusmenu object path exported by a tray item.
+pub async fn get_item_menu(
+    tray_manager: &TrayManager,
+    service_name: &str,
+) -> Result<(String, String), TrayError> {
+    let menu_path = tray_manager
+        .read()
+        .await
+        .get(service_name)
+        .ok_or_else(|| TrayError::ItemNotFound(format!("Tray item {} not found", service_name)))?
+        .menu_path
+        .clone()
+        .filter(|path| !path.is_empty() && path != "/")
+        .ok_or_else(|| TrayError::NoMenu(format!("Tray item {} has no menu", service_name)))?;
+
+    let (bus_name, _) = split_service_name(service_name);
+    Ok((bus_name.to_string(), menu_path))
+}
+
+/// Scale factor of the panel window, used to render tray icons for HiDPI.
+pub fn get_scale_factor(app_handle: &AppHandle) -> f64 {
+    app_handle
+        .get_webview_window("main")
+        .and_then(|window| window.scale_factor().ok())
+        .unwrap_or(1.0)
+}
+
 pub async fn emit_tray_update(app_handle: &AppHandle) {
     if let Err(e) = app_handle.emit("tray-update", ()) {
         eprintln!("[Tray] Error emitiendo evento tray-update: {}", e);
     }
 }
+
+pub async fn emit_tray_item_changed(app_handle: &AppHandle, item: &TrayItem) {
+    if let Err(e) = app_handle.emit("tray-item-changed", item) {
+        eprintln!("[Tray] Error emitiendo evento tray-item-changed: {}", e);
+    }
+}
+
+pub async fn emit_tray_menu_update(app_handle: &AppHandle, service_name: &str, revision: u32) {
+    let update = TrayMenuUpdate {
+        service_name: service_name.to_string(),
+        revision,
+    };
+
+    if let Err(e) = app_handle.emit("tray-menu-update", update) {
+        eprintln!("[Tray] Error emitiendo evento tray-menu-update: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::process::{Child, Command, Stdio};
+    use std::sync::Mutex;
+    use zbus::interface;
+
+    /// A private session bus, killed on drop.
+    struct DbusDaemon {
+        process: Child,
+        address: String,
+    }
+
+    impl DbusDaemon {
+        /// Starts a bus, or returns `None` when dbus-daemon is not installed.
+        fn start() -> Option<Self> {
+            let mut process = Command::new("dbus-daemon")
+                .args(["--session", "--nofork", "--print-address"])
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .spawn()
+                .ok()?;
+
+            let mut address = String::new();
+            BufReader::new(process.stdout.take()?).read_line(&mut address).ok()?;
+            Some(Self {
+                process,
+                address: address.trim().to_string(),
+            })
+        }
+    }
+
+    impl Drop for DbusDaemon {
+        fn drop(&mut self) {
+            let _ = self.process.kill();
+            let _ = self.process.wait();
+        }
+    }
+
+    /// A StatusNotifierItem that records the calls it receives.
+    #[derive(Clone, Default)]
+    struct StubItem {
+        calls: Arc<Mutex<Vec<String>>>,
+    }
+
+    #[interface(name = "org.kde.StatusNotifierItem")]
+    impl StubItem {
+        fn activate(&self, x: i32, y: i32) {
+            self.calls.lock().unwrap().push(format!("Activate({}, {})", x, y));
+        }
+
+        fn secondary_activate(&self, x: i32, y: i32) {
+            self.calls.lock().unwrap().push(format!("SecondaryActivate({}, {})", x, y));
+        }
+
+        fn context_menu(&self, x: i32, y: i32) {
+            self.calls.lock().unwrap().push(format!("ContextMenu({}, {})", x, y));
+        }
+
+        fn scroll(&self, delta: i32, orientation: String) {
+            self.calls.lock().unwrap().push(format!("Scroll({}, {})", delta, orientation));
+        }
+    }
+
+    fn stub_tray_item(service_name: &str) -> TrayItem {
+        TrayItem {
+            id: "stub".to_string(),
+            service_name: service_name.to_string(),
+            icon_name: None,
+            icon_data: None,
+            attention_frames: None,
+            title: None,
+            tooltip: None,
+            status: TrayStatus::Active,
+            category: TrayCategory::ApplicationStatus,
+            menu_path: None,
+            namespace: SniNamespace::Kde,
+            registered_name: None,
+            signal_watcher: None,
+            sequence: next_sequence(),
+        }
+    }
+
+    #[tokio::test]
+    async fn item_calls_reach_the_registered_item() {
+        let Some(bus) = DbusDaemon::start() else {
+            eprintln!("dbus-daemon is not installed, skipping");
+            return;
+        };
+        // get_item_proxy connects to the session bus
+        std::env::set_var("DBUS_SESSION_BUS_ADDRESS", &bus.address);
+
+        let item = StubItem::default();
+        let connection = Connection::session().await.unwrap();
+        connection.object_server().at("/StatusNotifierItem", item.clone()).await.unwrap();
+        connection.request_name("org.example.StubItem").await.unwrap();
+
+        let service_name = "org.example.StubItem";
+        let tray_manager = create_tray_manager();
+        tray_manager
+            .write()
+            .await
+            .insert(service_name.to_string(), stub_tray_item(service_name));
+
+        activate_item(&tray_manager, service_name, 10, 20).await.unwrap();
+        secondary_activate_item(&tray_manager, service_name, 30, 40).await.unwrap();
+        item_context_menu(&tray_manager, service_name, 50, 60).await.unwrap();
+        scroll_item(&tray_manager, service_name, -120, "vertical").await.unwrap();
+
+        assert_eq!(
+            *item.calls.lock().unwrap(),
+            [
+                "Activate(10, 20)",
+                "SecondaryActivate(30, 40)",
+                "ContextMenu(50, 60)",
+                "Scroll(-120, vertical)",
+            ]
+        );
+
+        // Items the panel doesn't know about never reach the bus
+        let missing = activate_item(&tray_manager, "org.example.Missing", 0, 0).await;
+        assert!(matches!(missing, Err(TrayError::ItemNotFound(_))), "{:?}", missing);
+    }
+}
@@ -0,0 +1,501 @@
+use super::pixmap::SniPixmap;
+use super::tooltip::SniToolTip;
+use super::{TrayError, TrayStatus};
+use crate::config;
+use crate::tray::sni_watcher::SNI_WATCHER_SERVICE;
+use crate::tray::sni_watcher_interface::SNI_WATCHER_PATH;
+use futures_util::stream::StreamExt;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use tauri::{AppHandle, Emitter};
+use zbus::{
+    fdo::{self, DBusProxy},
+    interface,
+    zvariant::{ObjectPath, OwnedObjectPath, OwnedValue, Str, Structure},
+    Connection, Proxy, SignalContext,
+};
+
+pub const PANEL_ITEM_PATH: &str = "/StatusNotifierItem";
+pub const PANEL_MENU_PATH: &str = "/MenuBar";
+
+const PANEL_ITEM_ID: &str = "vpanel";
+const ICON_NORMAL: &str = "user-desktop";
+const ICON_ATTENTION: &str = "dialog-warning";
+
+const MENU_ID_MESSAGE: i32 = 1;
+const MENU_ID_SEPARATOR: i32 = 2;
+const MENU_ID_LOCKED: i32 = 3;
+
+/// Whether the panel is locked, kept across restarts.
+const LOCKED_POINTER: &str = "/panel/locked";
+
+/// Connection serving the panel item, set once it is registered.
+static PANEL_ITEM: OnceLock<Connection> = OnceLock::new();
+
+/// A `(ia{sv}av)` dbusmenu node.
+type MenuLayout = (i32, HashMap<String, OwnedValue>, Vec<OwnedValue>);
+
+/// What the panel reports through its own item.
+#[derive(Debug, Clone)]
+struct PanelState {
+    status: TrayStatus,
+    icon_name: String,
+    /// Shown as tooltip and as the first menu entry
+    message: String,
+    locked: bool,
+}
+
+impl Default for PanelState {
+    fn default() -> Self {
+        Self {
+            status: TrayStatus::Passive,
+            icon_name: ICON_NORMAL.to_string(),
+            message: String::new(),
+            locked: false,
+        }
+    }
+}
+
+type SharedPanelState = Arc<Mutex<PanelState>>;
+
+fn read_state(state: &SharedPanelState) -> PanelState {
+    state.lock().map(|state| state.clone()).unwrap_or_default()
+}
+
+/// `org.kde.StatusNotifierItem` for vpanel itself.
+struct PanelItem {
+    state: SharedPanelState,
+}
+
+#[interface(name = "org.kde.StatusNotifierItem")]
+impl PanelItem {
+    // The panel has nothing to open; everything is in the menu
+    async fn activate(&self, _x: i32, _y: i32) {}
+
+    async fn secondary_activate(&self, _x: i32, _y: i32) {}
+
+    async fn context_menu(&self, _x: i32, _y: i32) {}
+
+    async fn scroll(&self, _delta: i32, _orientation: String) {}
+
+    #[zbus(property)]
+    async fn category(&self) -> String {
+        "SystemServices".to_string()
+    }
+
+    #[zbus(property)]
+    async fn id(&self) -> String {
+        PANEL_ITEM_ID.to_string()
+    }
+
+    #[zbus(property)]
+    async fn title(&self) -> String {
+        PANEL_ITEM_ID.to_string()
+    }
+
+    #[zbus(property)]
+    async fn status(&self) -> String {
+        status_name(&read_state(&self.state).status).to_string()
+    }
+
+    #[zbus(property)]
+    async fn window_id(&self) -> u32 {
+        0
+    }
+
+    #[zbus(property)]
+    async fn icon_name(&self) -> String {
+        read_state(&self.state).icon_name
+    }
+
+    #[zbus(property)]
+    async fn icon_pixmap(&self) -> Vec<SniPixmap> {
+        Vec::new()
+    }
+
+    #[zbus(property)]
+    async fn overlay_icon_name(&self) -> String {
+        String::new()
+    }
+
+    #[zbus(property)]
+    async fn overlay_icon_pixmap(&self) -> Vec<SniPixmap> {
+        Vec::new()
+    }
+
+    #[zbus(property)]
+    async fn attention_icon_name(&self) -> String {
+        read_state(&self.state).icon_name
+    }
+
+    #[zbus(property)]
+    async fn attention_icon_pixmap(&self) -> Vec<SniPixmap> {
+        Vec::new()
+    }
+
+    #[zbus(property)]
+    async fn attention_movie_name(&self) -> String {
+        String::new()
+    }
+
+    #[zbus(property)]
+    async fn tool_tip(&self) -> SniToolTip {
+        let state = read_state(&self.state);
+        (state.icon_name, Vec::new(), PANEL_ITEM_ID.to_string(), state.message)
+    }
+
+    #[zbus(property)]
+    async fn icon_theme_path(&self) -> String {
+        String::new()
+    }
+
+    #[zbus(property)]
+    async fn menu(&self) -> OwnedObjectPath {
+        OwnedObjectPath::from(ObjectPath::from_static_str_unchecked(PANEL_MENU_PATH))
+    }
+
+    #[zbus(property)]
+    async fn item_is_menu(&self) -> bool {
+        true
+    }
+
+    #[zbus(signal)]
+    async fn new_title(ctxt: &SignalContext<'_>) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn new_icon(ctxt: &SignalContext<'_>) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn new_attention_icon(ctxt: &SignalContext<'_>) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn new_overlay_icon(ctxt: &SignalContext<'_>) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn new_tool_tip(ctxt: &SignalContext<'_>) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn new_status(ctxt: &SignalContext<'_>, status: &str) -> zbus::Result<()>;
+}
+
+/// `com.canonical.dbusmenu` of the panel item: the status message and the
+/// panel lock toggle.
+struct PanelMenu {
+    state: SharedPanelState,
+    app_handle: AppHandle,
+    revision: u32,
+}
+
+impl PanelMenu {
+    fn item_properties(&self, id: i32) -> Option<HashMap<String, OwnedValue>> {
+        let state = read_state(&self.state);
+        let mut properties = HashMap::new();
+
+        match id {
+            0 => {
+                properties.insert("children-display".to_string(), str_value("submenu"));
+            }
+            MENU_ID_MESSAGE => {
+                let label = if state.message.is_empty() {
+                    "Panel funcionando correctamente"
+                } else {
+                    state.message.as_str()
+                };
+                properties.insert("label".to_string(), str_value(label));
+                properties.insert("enabled".to_string(), OwnedValue::from(false));
+            }
+            MENU_ID_SEPARATOR => {
+                properties.insert("type".to_string(), str_value("separator"));
+            }
+            MENU_ID_LOCKED => {
+                properties.insert("label".to_string(), str_value("Bloquear panel"));
+                properties.insert("toggle-type".to_string(), str_value("checkmark"));
+                properties.insert("toggle-state".to_string(), OwnedValue::from(i32::from(state.locked)));
+            }
+            _ => return None,
+        }
+
+        Some(properties)
+    }
+
+    fn layout(&self, id: i32, depth: i32) -> fdo::Result<MenuLayout> {
+        let properties = self
+            .item_properties(id)
+            .ok_or_else(|| fdo::Error::InvalidArgs(format!("Elemento de menú desconocido: {}", id)))?;
+
+        let mut children = Vec::new();
+        if id == 0 && depth != 0 {
+            for child in [MENU_ID_MESSAGE, MENU_ID_SEPARATOR, MENU_ID_LOCKED] {
+                let layout = self.layout(child, depth - 1)?;
+                let value = OwnedValue::try_from(Structure::from(layout)).map_err(zbus::Error::from)?;
+                children.push(value);
+            }
+        }
+
+        Ok((id, properties, children))
+    }
+
+    async fn toggle_locked(&mut self, ctxt: &SignalContext<'_>) -> zbus::Result<()> {
+        let locked = match self.state.lock() {
+            Ok(mut state) => {
+                state.locked = !state.locked;
+                state.locked
+            }
+            Err(_) => return Ok(()),
+        };
+
+        // The config is written with tokio::fs, which needs the Tauri runtime
+        let app_handle = self.app_handle.clone();
+        tauri::async_runtime::spawn(async move {
+            let saved = config::update_config(&app_handle, |config| {
+                config::object_entry(config, "panel").insert("locked".to_string(), locked.into());
+                Ok(())
+            })
+            .await
+            .map_err(|e| e.to_string());
+            if let Err(e) = saved {
+                eprintln!("[Tray] Error guardando el bloqueo del panel: {}", e);
+            }
+        });
+
+        if let Err(e) = self.app_handle.emit("panel-lock-changed", locked) {
+            eprintln!("[Tray] Error emitiendo evento panel-lock-changed: {}", e);
+        }
+
+        self.revision += 1;
+        Self::layout_updated(ctxt, self.revision, 0).await
+    }
+}
+
+#[interface(name = "com.canonical.dbusmenu")]
+impl PanelMenu {
+    async fn get_layout(
+        &self,
+        parent_id: i32,
+        recursion_depth: i32,
+        _property_names: Vec<String>,
+    ) -> fdo::Result<(u32, MenuLayout)> {
+        Ok((self.revision, self.layout(parent_id, recursion_depth)?))
+    }
+
+    async fn get_group_properties(
+        &self,
+        ids: Vec<i32>,
+        _property_names: Vec<String>,
+    ) -> Vec<(i32, HashMap<String, OwnedValue>)> {
+        ids.into_iter()
+            .filter_map(|id| Some((id, self.item_properties(id)?)))
+            .collect()
+    }
+
+    async fn get_property(&self, id: i32, name: String) -> fdo::Result<OwnedValue> {
+        self.item_properties(id)
+            .and_then(|mut properties| properties.remove(&name))
+            .ok_or_else(|| fdo::Error::InvalidArgs(format!("Propiedad desconocida: {} de {}", name, id)))
+    }
+
+    async fn event(
+        &mut self,
+        id: i32,
+        event_id: String,
+        _data: OwnedValue,
+        _timestamp: u32,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+    ) -> fdo::Result<()> {
+        if id == MENU_ID_LOCKED && event_id == "clicked" {
+            self.toggle_locked(&ctxt).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn event_group(
+        &mut self,
+        events: Vec<(i32, String, OwnedValue, u32)>,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+    ) -> fdo::Result<Vec<i32>> {
+        let mut id_errors = Vec::new();
+        for (id, event_id, _data, _timestamp) in events {
+            if self.item_properties(id).is_none() {
+                id_errors.push(id);
+            } else if id == MENU_ID_LOCKED && event_id == "clicked" {
+                self.toggle_locked(&ctxt).await?;
+            }
+        }
+
+        Ok(id_errors)
+    }
+
+    async fn about_to_show(&self, _id: i32) -> bool {
+        false
+    }
+
+    async fn about_to_show_group(&self, _ids: Vec<i32>) -> (Vec<i32>, Vec<i32>) {
+        (Vec::new(), Vec::new())
+    }
+
+    #[zbus(property)]
+    async fn version(&self) -> u32 {
+        3
+    }
+
+    #[zbus(property)]
+    async fn text_direction(&self) -> String {
+        "ltr".to_string()
+    }
+
+    #[zbus(property)]
+    async fn status(&self) -> String {
+        "normal".to_string()
+    }
+
+    #[zbus(property)]
+    async fn icon_theme_path(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    #[zbus(signal)]
+    async fn items_properties_updated(
+        ctxt: &SignalContext<'_>,
+        updated_props: Vec<(i32, HashMap<String, OwnedValue>)>,
+        removed_props: Vec<(i32, Vec<String>)>,
+    ) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn layout_updated(ctxt: &SignalContext<'_>, revision: u32, parent: i32) -> zbus::Result<()>;
+}
+
+fn str_value(value: &str) -> OwnedValue {
+    OwnedValue::from(Str::from(value.to_string()))
+}
+
+fn status_name(status: &TrayStatus) -> &'static str {
+    match status {
+        TrayStatus::Active => "Active",
+        TrayStatus::Passive => "Passive",
+        TrayStatus::NeedsAttention => "NeedsAttention",
+    }
+}
+
+/// Serves vpanel's own item on a dedicated connection and registers it with
+/// whichever watcher owns the name, now and whenever the watcher restarts.
+pub async fn start_panel_item(app_handle: AppHandle) -> Result<(), Box<dyn std::error::Error>> {
+    if PANEL_ITEM.get().is_some() {
+        return Ok(());
+    }
+
+    let connection = Connection::session().await?;
+    let locked: bool = config::read_section(&app_handle, LOCKED_POINTER).await;
+    let state = SharedPanelState::new(Mutex::new(PanelState {
+        locked,
+        ..Default::default()
+    }));
+
+    connection
+        .object_server()
+        .at(PANEL_ITEM_PATH, PanelItem { state: state.clone() })
+        .await?;
+    connection
+        .object_server()
+        .at(
+            PANEL_MENU_PATH,
+            PanelMenu {
+                state,
+                app_handle: app_handle.clone(),
+                revision: 0,
+            },
+        )
+        .await?;
+
+    let item_name = format!("org.kde.StatusNotifierItem-{}-1", std::process::id());
+    connection.request_name(item_name.as_str()).await?;
+
+    let mut owner_stream = DBusProxy::new(&connection)
+        .await?
+        .receive_name_owner_changed_with_args(&[(0, SNI_WATCHER_SERVICE)])
+        .await?;
+
+    if let Err(e) = register_with_watcher(&connection, &item_name).await {
+        eprintln!("[Tray] Error registrando el item del panel: {}", e);
+    }
+
+    tokio::spawn({
+        let connection = connection.clone();
+
+        async move {
+            while let Some(signal) = owner_stream.next().await {
+                let Ok(args) = signal.args() else {
+                    continue;
+                };
+                if args.new_owner().is_none() {
+                    continue;
+                }
+
+                if let Err(e) = register_with_watcher(&connection, &item_name).await {
+                    eprintln!("[Tray] Error registrando el item del panel: {}", e);
+                }
+            }
+        }
+    });
+
+    let _ = PANEL_ITEM.set(connection);
+
+    if let Err(e) = app_handle.emit("panel-lock-changed", locked) {
+        eprintln!("[Tray] Error emitiendo evento panel-lock-changed: {}", e);
+    }
+    Ok(())
+}
+
+async fn register_with_watcher(connection: &Connection, item_name: &str) -> zbus::Result<()> {
+    let watcher = Proxy::new(connection, SNI_WATCHER_SERVICE, SNI_WATCHER_PATH, SNI_WATCHER_SERVICE).await?;
+    watcher.call("RegisterStatusNotifierItem", &(item_name,)).await
+}
+
+/// Updates the status shown by the panel item. Without an icon name, a
+/// default one for the status is used.
+pub async fn set_status(
+    status: TrayStatus,
+    icon_name: Option<&str>,
+    message: &str,
+) -> Result<(), TrayError> {
+    let connection = PANEL_ITEM
+        .get()
+        .ok_or_else(|| TrayError::ProtocolUnavailable("El item del panel no está iniciado".to_string()))?;
+    let icon_name = icon_name.unwrap_or(match status {
+        TrayStatus::NeedsAttention => ICON_ATTENTION,
+        TrayStatus::Active | TrayStatus::Passive => ICON_NORMAL,
+    });
+
+    let item_ref = connection
+        .object_server()
+        .interface::<_, PanelItem>(PANEL_ITEM_PATH)
+        .await?;
+    if let Ok(mut state) = item_ref.get().await.state.lock() {
+        state.status = status.clone();
+        state.icon_name = icon_name.to_string();
+        state.message = message.to_string();
+    }
+
+    let ctxt = item_ref.signal_context();
+    PanelItem::new_status(ctxt, status_name(&status)).await?;
+    PanelItem::new_icon(ctxt).await?;
+    PanelItem::new_attention_icon(ctxt).await?;
+    PanelItem::new_tool_tip(ctxt).await?;
+
+    let menu_ref = connection
+        .object_server()
+        .interface::<_, PanelMenu>(PANEL_MENU_PATH)
+        .await?;
+    let mut menu = menu_ref.get_mut().await;
+    menu.revision += 1;
+    PanelMenu::layout_updated(menu_ref.signal_context(), menu.revision, 0).await?;
+
+    Ok(())
+}
+
+/// Reports a failing backend through the panel item, if it is running.
+pub async fn report_error(message: &str) {
+    if let Err(e) = set_status(TrayStatus::NeedsAttention, None, message).await {
+        eprintln!("[Tray] Error actualizando el estado del panel: {}", e);
+    }
+}
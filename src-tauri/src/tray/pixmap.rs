@@ -0,0 +1,137 @@
+use super::TrayIconFrame;
+use base64::{Engine as _, engine::general_purpose};
+use image::{codecs::gif::GifDecoder, imageops::FilterType, AnimationDecoder, RgbaImage};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// One entry of an SNI pixmap property: width, height and ARGB32 data.
+pub type SniPixmap = (i32, i32, Vec<u8>);
+
+/// Service name, logical icon size and scale in percent.
+type IconCacheKey = (String, u32, u32);
+
+/// Rendered tray icons as base64 PNG.
+static ICON_CACHE: Mutex<Option<HashMap<IconCacheKey, String>>> = Mutex::new(None);
+
+/// Picks the smallest pixmap covering `target` pixels, or the largest one
+/// when every pixmap is smaller.
+pub fn select_pixmap(pixmaps: &[SniPixmap], target: u32) -> Option<&SniPixmap> {
+    let valid = pixmaps
+        .iter()
+        .filter(|(width, height, data)| *width > 0 && *height > 0 && data.len() >= pixmap_len(*width, *height));
+
+    let covering = valid
+        .clone()
+        .filter(|(width, height, _)| (*width).max(*height) as u32 >= target)
+        .min_by_key(|(width, height, _)| (*width).max(*height));
+
+    covering.or_else(|| valid.max_by_key(|(width, height, _)| (*width).max(*height)))
+}
+
+/// Converts a pixmap to RGBA. The spec sends ARGB32 in network byte order.
+pub fn pixmap_to_image(pixmap: &SniPixmap) -> Option<RgbaImage> {
+    let (width, height, data) = pixmap;
+    let len = pixmap_len(*width, *height);
+
+    let rgba_data = data
+        .get(..len)?
+        .chunks_exact(4)
+        .flat_map(|argb| [argb[1], argb[2], argb[3], argb[0]])
+        .collect();
+
+    RgbaImage::from_raw(*width as u32, *height as u32, rgba_data)
+}
+
+fn pixmap_len(width: i32, height: i32) -> usize {
+    width.max(0) as usize * height.max(0) as usize * 4
+}
+
+/// Shrinks `img` so its longest side is `target`, keeping the aspect ratio.
+/// Smaller images are returned untouched; the webview scales them up.
+pub fn fit_image(img: RgbaImage, target: u32) -> RgbaImage {
+    let longest = img.width().max(img.height());
+    if target == 0 || longest <= target {
+        return img;
+    }
+
+    let width = (img.width() * target / longest).max(1);
+    let height = (img.height() * target / longest).max(1);
+    image::imageops::resize(&img, width, height, FilterType::Lanczos3)
+}
+
+pub fn encode_png_base64(img: &RgbaImage) -> Result<String, Box<dyn std::error::Error>> {
+    let mut buffer = Vec::new();
+    img.write_to(&mut std::io::Cursor::new(&mut buffer), image::ImageFormat::Png)?;
+
+    Ok(general_purpose::STANDARD.encode(&buffer))
+}
+
+/// Converts the best-fitting pixmap and fits it to `target` pixels.
+pub fn render_image(pixmaps: &[SniPixmap], target: u32) -> Option<RgbaImage> {
+    let img = pixmap_to_image(select_pixmap(pixmaps, target)?)?;
+    Some(fit_image(img, target))
+}
+
+/// Renders the best-fitting pixmap at `target` pixels as base64 PNG.
+pub fn render_pixmaps(pixmaps: &[SniPixmap], target: u32) -> Option<String> {
+    encode_png_base64(&render_image(pixmaps, target)?).ok()
+}
+
+/// Draws `overlay` as a badge over the bottom-right quarter of `base`.
+pub fn composite_overlay(base: &mut RgbaImage, overlay: &RgbaImage) {
+    let badge = fit_image(overlay.clone(), base.width().max(base.height()) / 2);
+    let x = base.width().saturating_sub(badge.width());
+    let y = base.height().saturating_sub(badge.height());
+
+    image::imageops::overlay(base, &badge, x as i64, y as i64);
+}
+
+/// Decodes an animated GIF into frames fitted to `target` pixels.
+pub fn load_movie_frames(path: &Path, target: u32) -> Option<Vec<TrayIconFrame>> {
+    let file = BufReader::new(File::open(path).ok()?);
+    let frames = GifDecoder::new(file).ok()?.into_frames().collect_frames().ok()?;
+
+    let frames: Vec<TrayIconFrame> = frames
+        .into_iter()
+        .filter_map(|frame| {
+            let (numerator, denominator) = frame.delay().numer_denom_ms();
+            let delay_ms = numerator / denominator.max(1);
+            let data = encode_png_base64(&fit_image(frame.into_buffer(), target)).ok()?;
+            Some(TrayIconFrame { data, delay_ms })
+        })
+        .collect();
+
+    (!frames.is_empty()).then_some(frames)
+}
+
+fn scale_key(scale: f64) -> u32 {
+    (scale * 100.0).round() as u32
+}
+
+pub fn cached_icon(service_name: &str, size: u32, scale: f64) -> Option<String> {
+    let cache = ICON_CACHE.lock().ok()?;
+    cache
+        .as_ref()?
+        .get(&(service_name.to_string(), size, scale_key(scale)))
+        .cloned()
+}
+
+pub fn cache_icon(service_name: &str, size: u32, scale: f64, data: String) {
+    if let Ok(mut cache) = ICON_CACHE.lock() {
+        cache
+            .get_or_insert_with(HashMap::new)
+            .insert((service_name.to_string(), size, scale_key(scale)), data);
+    }
+}
+
+/// Drops every rendered size of an item's icon, e.g. after `NewIcon`.
+pub fn invalidate_icons(service_name: &str) {
+    if let Ok(mut cache) = ICON_CACHE.lock() {
+        if let Some(cache) = cache.as_mut() {
+            cache.retain(|(service, _, _), _| service != service_name);
+        }
+    }
+}
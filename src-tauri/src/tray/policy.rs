@@ -0,0 +1,105 @@
+use super::TrayItem;
+use crate::config::{self, SortMode};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tauri::AppHandle;
+
+/// Where an item is shown. Items without a rule stay in the panel, passive
+/// ones included; only `Overflow` collapses them behind the chevron.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TrayVisibility {
+    #[default]
+    AlwaysVisible,
+    Hidden,
+    Overflow,
+}
+
+/// Rule for one item, matched by its SNI `Id`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrayItemPolicy {
+    #[serde(default)]
+    pub visibility: TrayVisibility,
+    /// Explicit position; items without one follow the ordered ones
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub order: Option<i32>,
+}
+
+/// `panel.tray` section of the shared vasak configuration.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TrayPolicy {
+    #[serde(default)]
+    pub items: HashMap<String, TrayItemPolicy>,
+    /// Order of the items without an explicit position
+    #[serde(default)]
+    pub sort: SortMode,
+}
+
+/// Tray items as shown by the panel: inline, or collapsed behind the chevron.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TrayItemList {
+    pub items: Vec<TrayItem>,
+    pub overflow: Vec<TrayItem>,
+}
+
+impl TrayPolicy {
+    fn item_policy(&self, item: &TrayItem) -> TrayItemPolicy {
+        self.items.get(&item.id).cloned().unwrap_or_default()
+    }
+
+    /// Drops hidden items, orders the rest and splits off the overflow.
+    pub fn apply(&self, mut items: Vec<TrayItem>, sort: SortMode) -> TrayItemList {
+        items.retain(|item| self.item_policy(item).visibility != TrayVisibility::Hidden);
+
+        // Explicitly ordered items first, the rest by `sort`; ties keep the
+        // order in which items appeared
+        items.sort_by_cached_key(|item| {
+            let order = self.item_policy(item).order;
+            let key = match sort {
+                SortMode::Creation | SortMode::Workspace => String::new(),
+                SortMode::AppId => item.id.to_lowercase(),
+                SortMode::Title => item.title.as_deref().unwrap_or(&item.id).to_lowercase(),
+            };
+            (order.is_none(), order, key, item.sequence)
+        });
+
+        let (overflow, items) = items
+            .into_iter()
+            .partition(|item| self.item_policy(item).visibility == TrayVisibility::Overflow);
+
+        TrayItemList { items, overflow }
+    }
+}
+
+/// Reads the tray policy, falling back to the defaults when the config file
+/// is missing or has no `panel.tray` section.
+pub async fn load_policy(app_handle: &AppHandle) -> TrayPolicy {
+    config::read_section(app_handle, "/panel/tray").await
+}
+
+/// Stores the rule for `id` in the config file, keeping every other setting.
+pub async fn set_item_policy(
+    app_handle: &AppHandle,
+    id: &str,
+    policy: TrayItemPolicy,
+) -> Result<(), Box<dyn std::error::Error>> {
+    config::update_config(app_handle, |config| {
+        let tray = config::object_entry(config::object_entry(config, "panel"), "tray");
+
+        let mut items: HashMap<String, TrayItemPolicy> = tray
+            .get("items")
+            .cloned()
+            .and_then(|items| serde_json::from_value(items).ok())
+            .unwrap_or_default();
+
+        if policy == TrayItemPolicy::default() {
+            items.remove(id);
+        } else {
+            items.insert(id.to_string(), policy);
+        }
+
+        tray.insert("items".to_string(), serde_json::to_value(&items)?);
+        Ok(())
+    })
+    .await
+}
@@ -0,0 +1,134 @@
+use super::{split_service_name, TrayManager, TrayMenuCache};
+use crate::tray::sni_watcher::{SniWatcher, SNI_WATCHER_SERVICE};
+use futures_util::stream::StreamExt;
+use tauri::AppHandle;
+use tokio::task::AbortHandle;
+use zbus::{fdo::DBusProxy, proxy, proxy::CacheProperties, Connection};
+
+#[proxy(
+    interface = "org.kde.StatusNotifierWatcher",
+    default_service = "org.kde.StatusNotifierWatcher",
+    default_path = "/StatusNotifierWatcher"
+)]
+trait StatusNotifierWatcher {
+    /// RegisterStatusNotifierHost method
+    fn register_status_notifier_host(&self, service: &str) -> zbus::Result<()>;
+
+    /// RegisteredStatusNotifierItems property
+    #[zbus(property)]
+    fn registered_status_notifier_items(&self) -> zbus::Result<Vec<String>>;
+
+    /// StatusNotifierItemRegistered signal
+    #[zbus(signal)]
+    fn status_notifier_item_registered(&self, service: &str) -> zbus::Result<()>;
+
+    /// StatusNotifierItemUnregistered signal
+    #[zbus(signal)]
+    fn status_notifier_item_unregistered(&self, service: &str) -> zbus::Result<()>;
+}
+
+/// Bus name vpanel registers as a StatusNotifierHost under.
+pub fn host_name() -> String {
+    format!("org.kde.StatusNotifierHost-{}", std::process::id())
+}
+
+/// Runs vpanel as a plain StatusNotifierHost when another process already
+/// owns the watcher name, mirroring that watcher's items into `TrayManager`.
+/// Returns the handle of the task following the watcher's signals.
+pub async fn start_host(
+    connection: &Connection,
+    tray_manager: &TrayManager,
+    menu_cache: &TrayMenuCache,
+    app_handle: &AppHandle,
+) -> Result<AbortHandle, Box<dyn std::error::Error>> {
+    let host_name = host_name();
+    connection.request_name(host_name.as_str()).await?;
+
+    let watcher = StatusNotifierWatcherProxy::builder(connection)
+        .cache_properties(CacheProperties::No)
+        .build()
+        .await?;
+
+    // Subscribe before the initial sync so no registration slips in between
+    let mut registered_stream = watcher.receive_status_notifier_item_registered().await?;
+    let mut unregistered_stream = watcher.receive_status_notifier_item_unregistered().await?;
+    let mut owner_stream = DBusProxy::new(connection)
+        .await?
+        .receive_name_owner_changed_with_args(&[(0, SNI_WATCHER_SERVICE)])
+        .await?;
+
+    sync_with_watcher(connection, &watcher, &host_name, tray_manager, menu_cache, app_handle).await?;
+
+    let task = tokio::spawn({
+        let connection = connection.clone();
+        let tray_manager = tray_manager.clone();
+        let menu_cache = menu_cache.clone();
+        let app_handle = app_handle.clone();
+
+        async move {
+            loop {
+                tokio::select! {
+                    Some(signal) = registered_stream.next() => {
+                        if let Ok(args) = signal.args() {
+                            if let Err(e) = SniWatcher::register_item(&connection, &tray_manager, &menu_cache, &app_handle, args.service(), None).await {
+                                eprintln!("[SNI] Error registrando item {}: {}", args.service(), e);
+                            }
+                        }
+                    }
+                    Some(signal) = unregistered_stream.next() => {
+                        if let Ok(args) = signal.args() {
+                            // The watcher's string may use a well-known name instead of our key
+                            let (bus_name, object_path) = split_service_name(args.service());
+                            for service_name in SniWatcher::items_owned_by(&tray_manager, bus_name).await {
+                                if split_service_name(&service_name).1 == object_path {
+                                    SniWatcher::unregister_item(&connection, &tray_manager, &menu_cache, &app_handle, &service_name).await;
+                                }
+                            }
+                        }
+                    }
+                    Some(signal) = owner_stream.next() => {
+                        let Ok(args) = signal.args() else {
+                            continue;
+                        };
+
+                        // A restarted watcher forgets its hosts and items: register again
+                        if args.new_owner().is_some() {
+                            println!("[SNI] Nuevo StatusNotifierWatcher detectado, sincronizando");
+                            let synced = sync_with_watcher(&connection, &watcher, &host_name, &tray_manager, &menu_cache, &app_handle)
+                                .await
+                                .map_err(|e| e.to_string());
+
+                            if let Err(e) = synced {
+                                eprintln!("[SNI] Error sincronizando con el watcher: {}", e);
+                            }
+                        } else {
+                            println!("[SNI] El StatusNotifierWatcher externo desapareció");
+                        }
+                    }
+                    else => break,
+                }
+            }
+        }
+    });
+
+    Ok(task.abort_handle())
+}
+
+async fn sync_with_watcher(
+    connection: &Connection,
+    watcher: &StatusNotifierWatcherProxy<'_>,
+    host_name: &str,
+    tray_manager: &TrayManager,
+    menu_cache: &TrayMenuCache,
+    app_handle: &AppHandle,
+) -> Result<(), Box<dyn std::error::Error>> {
+    watcher.register_status_notifier_host(host_name).await?;
+
+    for service in watcher.registered_status_notifier_items().await? {
+        if let Err(e) = SniWatcher::register_item(connection, tray_manager, menu_cache, app_handle, &service, None).await {
+            eprintln!("[SNI] Error registrando item existente {}: {}", service, e);
+        }
+    }
+
+    Ok(())
+}
@@ -1,4 +1,5 @@
-use zbus::proxy;
+use crate::tray::tooltip::SniToolTip;
+use zbus::{fdo::IntrospectableProxy, proxy, zvariant::OwnedObjectPath, Connection};
 
 #[proxy(
     interface = "org.kde.StatusNotifierItem",
@@ -12,6 +13,9 @@ trait SniItem {
     /// SecondaryActivate method  
     fn secondary_activate(&self, x: i32, y: i32) -> zbus::Result<()>;
 
+    /// ContextMenu method
+    fn context_menu(&self, x: i32, y: i32) -> zbus::Result<()>;
+
     /// Scroll method
     fn scroll(&self, delta: i32, orientation: &str) -> zbus::Result<()>;
 
@@ -65,13 +69,102 @@ trait SniItem {
 
     /// ToolTip property
     #[zbus(property)]
-    fn tool_tip(&self) -> zbus::Result<String>;
+    fn tool_tip(&self) -> zbus::Result<SniToolTip>;
+
+    /// IconThemePath property
+    #[zbus(property)]
+    fn icon_theme_path(&self) -> zbus::Result<String>;
 
     /// Menu property
     #[zbus(property)]
-    fn menu(&self) -> zbus::Result<String>;
+    fn menu(&self) -> zbus::Result<OwnedObjectPath>;
 
     /// ItemIsMenu property
     #[zbus(property)]
     fn item_is_menu(&self) -> zbus::Result<bool>;
+
+    /// NewTitle signal
+    #[zbus(signal)]
+    fn new_title(&self) -> zbus::Result<()>;
+
+    /// NewIcon signal
+    #[zbus(signal)]
+    fn new_icon(&self) -> zbus::Result<()>;
+
+    /// NewAttentionIcon signal
+    #[zbus(signal)]
+    fn new_attention_icon(&self) -> zbus::Result<()>;
+
+    /// NewOverlayIcon signal
+    #[zbus(signal)]
+    fn new_overlay_icon(&self) -> zbus::Result<()>;
+
+    /// NewToolTip signal
+    #[zbus(signal)]
+    fn new_tool_tip(&self) -> zbus::Result<()>;
+
+    /// NewStatus signal
+    #[zbus(signal)]
+    fn new_status(&self, status: &str) -> zbus::Result<()>;
+}
+
+/// Naming scheme of the StatusNotifier protocol. KDE's original names are the
+/// most common, but newer clients use the freedesktop ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SniNamespace {
+    #[default]
+    Kde,
+    Freedesktop,
+}
+
+impl SniNamespace {
+    pub const ALL: [SniNamespace; 2] = [SniNamespace::Kde, SniNamespace::Freedesktop];
+
+    pub fn item_interface(self) -> &'static str {
+        match self {
+            SniNamespace::Kde => "org.kde.StatusNotifierItem",
+            SniNamespace::Freedesktop => "org.freedesktop.StatusNotifierItem",
+        }
+    }
+
+    pub fn watcher_service(self) -> &'static str {
+        match self {
+            SniNamespace::Kde => "org.kde.StatusNotifierWatcher",
+            SniNamespace::Freedesktop => "org.freedesktop.StatusNotifierWatcher",
+        }
+    }
+
+    /// Namespace of a well-known item name such as
+    /// `org.kde.StatusNotifierItem-1234-1`.
+    pub fn from_item_service(service_name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|namespace| service_name.starts_with(namespace.item_interface()))
+    }
+
+    /// Asks the item which interface it implements, falling back to its bus
+    /// name and then to the KDE interface.
+    pub async fn detect(connection: &Connection, bus_name: &str, path: &str) -> Self {
+        let introspected = async {
+            let proxy = IntrospectableProxy::builder(connection)
+                .destination(bus_name.to_string())?
+                .path(path.to_string())?
+                .build()
+                .await?;
+            Ok::<_, zbus::Error>(proxy.introspect().await?)
+        };
+
+        if let Ok(xml) = introspected.await {
+            let implements = |namespace: SniNamespace| {
+                let interface = namespace.item_interface();
+                xml.contains(&format!("\"{}\"", interface)) || xml.contains(&format!("'{}'", interface))
+            };
+
+            if let Some(namespace) = Self::ALL.into_iter().find(|namespace| implements(*namespace)) {
+                return namespace;
+            }
+        }
+
+        Self::from_item_service(bus_name).unwrap_or_default()
+    }
 }
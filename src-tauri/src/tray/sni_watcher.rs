@@ -1,47 +1,121 @@
-use super::{TrayManager, TrayItem, TrayStatus, TrayCategory, emit_tray_update};
-use crate::tray::sni_item::SniItemProxy;
+use super::{
+    TrayManager, TrayMenuCache, TrayItem, TrayIconFrame, TrayStatus, TrayCategory, TrayTooltip, emit_tray_item_changed,
+    emit_tray_update, get_scale_factor, next_sequence, split_service_name,
+};
+use crate::icon_theme::{self, IconTheme};
+use crate::tray::menu_parser::MenuParser;
+use crate::tray::panel_item;
+use crate::tray::pixmap;
+use crate::tray::sni_host;
+use crate::tray::sni_item::{SniItemProxy, SniNamespace};
+use crate::tray::sni_watcher_interface::{self, FreedesktopStatusNotifierWatcher, StatusNotifierWatcher, SNI_WATCHER_PATH};
 use tauri::AppHandle;
-use zbus::{Connection, MessageStream, MatchRule, MessageType};
+use serde::Serialize;
+use tokio::sync::Mutex;
+use tokio::task::AbortHandle;
+use zbus::{fdo::DBusProxy, names::BusName, proxy::CacheProperties, Connection, MessageStream, MatchRule, MessageType};
 use futures_util::stream::StreamExt;
-use base64::{Engine as _, engine::general_purpose};
-
-const SNI_WATCHER_SERVICE: &str = "org.kde.StatusNotifierWatcher";
-const SNI_WATCHER_PATH: &str = "/StatusNotifierWatcher";
-const SNI_WATCHER_INTERFACE: &str = "org.kde.StatusNotifierWatcher";
+use image::RgbaImage;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// The KDE watcher name decides between watcher and host mode.
+pub const SNI_WATCHER_SERVICE: &str = "org.kde.StatusNotifierWatcher";
+/// Logical size of tray icons in the panel, before the HiDPI scale.
+const TRAY_ICON_SIZE: u32 = 16;
+
+/// How vpanel takes part in the StatusNotifier protocol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SniMode {
+    /// vpanel owns the watcher name and serves the interface itself
+    Watcher,
+    /// Another process owns the watcher name; vpanel only registers as a host
+    Host,
+}
 
 pub struct SniWatcher {
     connection: Connection,
+    mode: SniMode,
     tray_manager: TrayManager,
+    menu_cache: TrayMenuCache,
     app_handle: AppHandle,
 }
 
 impl SniWatcher {
-    pub async fn new(tray_manager: TrayManager, app_handle: AppHandle) -> Result<Self, Box<dyn std::error::Error>> {
+    pub async fn new(
+        tray_manager: TrayManager,
+        menu_cache: TrayMenuCache,
+        app_handle: AppHandle,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
         let connection = Connection::session().await?;
-        
-        // Register as StatusNotifierWatcher
+
+        // Serve the interfaces before taking the names so early callers get a reply
+        connection
+            .object_server()
+            .at(
+                SNI_WATCHER_PATH,
+                StatusNotifierWatcher::new(tray_manager.clone(), menu_cache.clone(), app_handle.clone()),
+            )
+            .await?;
         connection
-            .request_name(SNI_WATCHER_SERVICE)
+            .object_server()
+            .at(
+                SNI_WATCHER_PATH,
+                FreedesktopStatusNotifierWatcher::new(tray_manager.clone(), menu_cache.clone(), app_handle.clone()),
+            )
             .await?;
 
+        // Register as StatusNotifierWatcher, or fall back to host mode
+        let mode = match connection.request_name(SNI_WATCHER_SERVICE).await {
+            Ok(()) => SniMode::Watcher,
+            Err(zbus::Error::NameTaken) => {
+                println!("[SNI] {} ya está en uso, funcionando como StatusNotifierHost", SNI_WATCHER_SERVICE);
+                let object_server = connection.object_server();
+                object_server.remove::<StatusNotifierWatcher, _>(SNI_WATCHER_PATH).await?;
+                object_server.remove::<FreedesktopStatusNotifierWatcher, _>(SNI_WATCHER_PATH).await?;
+                SniMode::Host
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        // The freedesktop name is best effort: another watcher may serve it
+        if mode == SniMode::Watcher {
+            let freedesktop_service = SniNamespace::Freedesktop.watcher_service();
+            match connection.request_name(freedesktop_service).await {
+                Ok(()) => {}
+                Err(zbus::Error::NameTaken) => {
+                    println!("[SNI] {} ya está en uso, se sirve solo {}", freedesktop_service, SNI_WATCHER_SERVICE);
+                    connection
+                        .object_server()
+                        .remove::<FreedesktopStatusNotifierWatcher, _>(SNI_WATCHER_PATH)
+                        .await?;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        // vpanel is the host of its own watcher
+        if mode == SniMode::Watcher {
+            connection.request_name(sni_host::host_name().as_str()).await?;
+        }
+
         Ok(Self {
             connection,
+            mode,
             tray_manager,
+            menu_cache,
             app_handle,
         })
     }
 
-    pub async fn start_watching(&self) -> Result<(), Box<dyn std::error::Error>> {
-        // Listen for StatusNotifierItem registrations
-        let rule = MatchRule::builder()
-            .msg_type(MessageType::MethodCall)
-            .interface(SNI_WATCHER_INTERFACE)?
-            .member("RegisterStatusNotifierItem")?
-            .build();
-
-        let mut stream = MessageStream::for_match_rule(rule, &self.connection, None).await?;
+    pub fn mode(&self) -> SniMode {
+        self.mode
+    }
 
-        // Also listen for service name changes
+    /// Starts following the bus and returns the handles of the spawned tasks,
+    /// so they can be cancelled when the watcher stops.
+    pub async fn start_watching(&self) -> Result<Vec<AbortHandle>, Box<dyn std::error::Error>> {
+        // Listen for service name changes
         let name_owner_rule = MatchRule::builder()
             .msg_type(MessageType::Signal)
             .interface("org.freedesktop.DBus")?
@@ -50,101 +124,309 @@ impl SniWatcher {
 
         let mut name_stream = MessageStream::for_match_rule(name_owner_rule, &self.connection, None).await?;
 
-        tokio::spawn({
+        let name_task = tokio::spawn({
             let tray_manager = self.tray_manager.clone();
+            let menu_cache = self.menu_cache.clone();
             let app_handle = self.app_handle.clone();
             let connection = self.connection.clone();
             
             async move {
                 loop {
-                    tokio::select! {
-                        Some(msg) = stream.next() => {
-                            if let Ok(message) = msg {
-                                if let Ok(service_name) = message.body().deserialize::<&str>() {
-                                    if let Err(e) = Self::register_item(&connection, &tray_manager, &app_handle, service_name).await {
-                                        eprintln!("[SNI] Error registrando item {}: {}", service_name, e);
-                                    }
-                                }
-                            }
+                    let Some(Ok(message)) = name_stream.next().await else {
+                        break;
+                    };
+
+                    if let Ok((name, _old_owner, new_owner)) = message.body().deserialize::<(&str, &str, &str)>() {
+                        if !new_owner.is_empty() {
+                            continue;
+                        }
+
+                        for service_name in Self::items_owned_by(&tray_manager, name).await {
+                            Self::unregister_item(&connection, &tray_manager, &menu_cache, &app_handle, &service_name).await;
                         }
-                        Some(msg) = name_stream.next() => {
-                            if let Ok(message) = msg {
-                                if let Ok((name, _old_owner, new_owner)) = message.body().deserialize::<(&str, &str, &str)>() {
-                                    if new_owner.is_empty() && name.starts_with("org.kde.StatusNotifierItem") {
-                                        Self::unregister_item(&tray_manager, &app_handle, name).await;
-                                    }
-                                }
-                            }
+
+                        if let Err(e) = sni_watcher_interface::remove_host(&connection, name).await {
+                            eprintln!("[SNI] Error desregistrando host {}: {}", name, e);
                         }
                     }
                 }
             }
         });
 
-        // Discover existing StatusNotifierItems
-        self.discover_existing_items().await?;
+        let mut tasks = vec![name_task.abort_handle()];
+        let started = match self.mode {
+            // Discover existing StatusNotifierItems
+            SniMode::Watcher => self.discover_existing_items().await,
+            SniMode::Host => {
+                sni_host::start_host(&self.connection, &self.tray_manager, &self.menu_cache, &self.app_handle)
+                    .await
+                    .map(|task| tasks.push(task))
+            }
+        };
+
+        if let Err(e) = started {
+            name_task.abort();
+            return Err(e);
+        }
 
-        Ok(())
+        Ok(tasks)
     }
 
-    async fn register_item(
+    /// Gives up the names taken by `new`, so another process can take over.
+    pub async fn release_names(&self) {
+        let names = match self.mode {
+            SniMode::Watcher => vec![
+                SNI_WATCHER_SERVICE.to_string(),
+                SniNamespace::Freedesktop.watcher_service().to_string(),
+                sni_host::host_name(),
+            ],
+            SniMode::Host => vec![sni_host::host_name()],
+        };
+
+        for name in names {
+            if let Err(e) = self.connection.release_name(name.as_str()).await {
+                eprintln!("[SNI] Error liberando el nombre {}: {}", name, e);
+            }
+        }
+    }
+
+    /// Registers the item announced as `service` and returns the key it is
+    /// stored under. `sender` is the unique name of the registering client,
+    /// needed when `service` is only an object path.
+    pub(super) async fn register_item(
         connection: &Connection,
         tray_manager: &TrayManager,
+        menu_cache: &TrayMenuCache,
         app_handle: &AppHandle,
-        service_name: &str,
-    ) -> Result<(), Box<dyn std::error::Error>> {
+        service: &str,
+        sender: Option<&str>,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let (service_name, registered_name) = Self::resolve_item_service(connection, service, sender).await?;
+        let service_name = service_name.as_str();
         println!("[SNI] Registrando item: {}", service_name);
 
-        let (bus_name, object_path) = if service_name.contains('/') {
-            let parts: Vec<&str> = service_name.splitn(2, '/').collect();
-            (parts[0], format!("/{}", parts[1]))
-        } else {
-            (service_name, "/StatusNotifierItem".to_string())
-        };
+        let (bus_name, object_path) = split_service_name(service_name);
+        let namespace = SniNamespace::detect(connection, bus_name, &object_path).await;
 
+        // Items announce changes through NewIcon/NewTitle/... instead of
+        // PropertiesChanged, so cached properties would go stale
         let proxy = SniItemProxy::builder(connection)
-            .destination(bus_name)?
+            .destination(bus_name.to_string())?
             .path(object_path)?
+            .interface(namespace.item_interface())?
+            .cache_properties(CacheProperties::No)
             .build()
             .await?;
 
-        let item = Self::create_tray_item_from_proxy(&proxy, service_name).await?;
+        // A re-registered service may come back with different icons
+        pixmap::invalidate_icons(service_name);
+        let scale = get_scale_factor(app_handle);
+        let mut item = Self::create_tray_item_from_proxy(&proxy, service_name, namespace, scale).await?;
+        item.registered_name = registered_name;
+
+        match Self::watch_item(proxy, service_name, tray_manager, app_handle).await {
+            Ok(watcher) => item.signal_watcher = Some(watcher),
+            Err(e) => eprintln!("[SNI] Error suscribiendo a señales de {}: {}", service_name, e),
+        }
 
-        {
+        if let Some(menu_path) = item.menu_path.clone().filter(|path| !path.is_empty() && path != "/") {
+            if let Err(e) = MenuParser::watch_menu(
+                connection.clone(),
+                menu_cache.clone(),
+                app_handle.clone(),
+                service_name.to_string(),
+                bus_name.to_string(),
+                menu_path,
+            ).await {
+                eprintln!("[SNI] Error suscribiendo al menú de {}: {}", service_name, e);
+            }
+        }
+
+        let previous = {
             let mut manager = tray_manager.write().await;
-            manager.insert(service_name.to_string(), item);
+            // A re-registering item keeps its place
+            if let Some(existing) = manager.get(service_name) {
+                item.sequence = existing.sequence;
+            }
+            manager.insert(service_name.to_string(), item)
+        };
+
+        if let Some(watcher) = previous.and_then(|item| item.signal_watcher) {
+            watcher.abort();
         }
 
         emit_tray_update(app_handle).await;
-        Ok(())
+        Ok(service_name.to_string())
+    }
+
+    /// Resolves the string passed to `RegisterStatusNotifierItem` into the
+    /// `<unique name><object path>` key, plus the well-known name the item
+    /// registered with, if any.
+    async fn resolve_item_service(
+        connection: &Connection,
+        service: &str,
+        sender: Option<&str>,
+    ) -> Result<(String, Option<String>), Box<dyn std::error::Error>> {
+        // libappindicator registers with just its object path
+        if service.starts_with('/') {
+            let sender = sender.ok_or_else(|| format!("Item {} registered without a sender", service))?;
+            return Ok((format!("{}{}", sender, service), None));
+        }
+
+        let (bus_name, object_path) = split_service_name(service);
+        if bus_name.starts_with(':') {
+            return Ok((format!("{}{}", bus_name, object_path), None));
+        }
+
+        let owner = DBusProxy::new(connection)
+            .await?
+            .get_name_owner(BusName::try_from(bus_name)?)
+            .await?;
+        Ok((format!("{}{}", owner, object_path), Some(bus_name.to_string())))
     }
 
-    async fn unregister_item(tray_manager: &TrayManager, app_handle: &AppHandle, service_name: &str) {
+    /// Keys of the items served by `bus_name`, matched against both their
+    /// unique name and the well-known name they registered with.
+    pub(super) async fn items_owned_by(tray_manager: &TrayManager, bus_name: &str) -> Vec<String> {
+        tray_manager
+            .read()
+            .await
+            .iter()
+            .filter(|(service_name, item)| {
+                split_service_name(service_name).0 == bus_name || item.registered_name.as_deref() == Some(bus_name)
+            })
+            .map(|(service_name, _)| service_name.clone())
+            .collect()
+    }
+
+    pub(super) async fn unregister_item(
+        connection: &Connection,
+        tray_manager: &TrayManager,
+        menu_cache: &TrayMenuCache,
+        app_handle: &AppHandle,
+        service_name: &str,
+    ) {
         println!("[SNI] Desregistrando item: {}", service_name);
         
-        {
+        let removed = {
             let mut manager = tray_manager.write().await;
-            manager.remove(service_name);
+            manager.remove(service_name)
+        };
+
+        if let Some(item) = removed {
+            if let Some(watcher) = item.signal_watcher {
+                watcher.abort();
+            }
+
+            if let Err(e) = sni_watcher_interface::emit_item_unregistered(connection, service_name).await {
+                eprintln!("[SNI] Error emitiendo StatusNotifierItemUnregistered: {}", e);
+            }
+        }
+
+        pixmap::invalidate_icons(service_name);
+
+        if let Some(state) = menu_cache.write().await.remove(service_name) {
+            if let Some(watcher) = state.watcher {
+                watcher.abort();
+            }
         }
 
         emit_tray_update(app_handle).await;
     }
 
+    /// Follows the item's change signals, re-reading only the property that
+    /// changed and emitting `tray-item-changed` with the updated item.
+    async fn watch_item(
+        proxy: SniItemProxy<'static>,
+        service_name: &str,
+        tray_manager: &TrayManager,
+        app_handle: &AppHandle,
+    ) -> Result<AbortHandle, Box<dyn std::error::Error>> {
+        let mut title_stream = proxy.receive_new_title().await?;
+        let mut icon_stream = proxy.receive_new_icon().await?;
+        let mut attention_icon_stream = proxy.receive_new_attention_icon().await?;
+        let mut overlay_icon_stream = proxy.receive_new_overlay_icon().await?;
+        let mut tool_tip_stream = proxy.receive_new_tool_tip().await?;
+        let mut status_stream = proxy.receive_new_status().await?;
+
+        let task = tokio::spawn({
+            let service_name = service_name.to_string();
+            let tray_manager = tray_manager.clone();
+            let app_handle = app_handle.clone();
+
+            async move {
+                loop {
+                    let change = tokio::select! {
+                        Some(_) = title_stream.next() => {
+                            let title = proxy.title().await.ok();
+                            ItemChange::Title(title)
+                        }
+                        Some(_) = icon_stream.next() => Self::read_icon_change(&proxy, &service_name, &app_handle).await,
+                        Some(_) = attention_icon_stream.next() => Self::read_icon_change(&proxy, &service_name, &app_handle).await,
+                        Some(_) = overlay_icon_stream.next() => Self::read_icon_change(&proxy, &service_name, &app_handle).await,
+                        Some(_) = tool_tip_stream.next() => {
+                            let tooltip = proxy.tool_tip().await.ok().and_then(TrayTooltip::from_sni);
+                            ItemChange::ToolTip(tooltip)
+                        }
+                        Some(signal) = status_stream.next() => {
+                            let status = match signal.args() {
+                                Ok(args) => Self::parse_status(args.status()),
+                                Err(_) => Self::parse_status(&proxy.status().await.unwrap_or_default()),
+                            };
+
+                            // Entering or leaving NeedsAttention swaps the icon
+                            pixmap::invalidate_icons(&service_name);
+                            let scale = get_scale_factor(&app_handle);
+                            let icon = Self::get_item_icon(&proxy, &service_name, scale, &status).await;
+                            ItemChange::Status(status, icon)
+                        }
+                        else => break,
+                    };
+
+                    let updated = {
+                        let mut manager = tray_manager.write().await;
+                        manager.get_mut(&service_name).map(|item| {
+                            change.apply(item);
+                            item.clone()
+                        })
+                    };
+
+                    if let Some(item) = updated {
+                        emit_tray_item_changed(&app_handle, &item).await;
+                    }
+                }
+            }
+        });
+
+        Ok(task.abort_handle())
+    }
+
+    async fn read_icon_change(proxy: &SniItemProxy<'_>, service_name: &str, app_handle: &AppHandle) -> ItemChange {
+        pixmap::invalidate_icons(service_name);
+        let status = Self::parse_status(&proxy.status().await.unwrap_or_default());
+        let icon = Self::get_item_icon(proxy, service_name, get_scale_factor(app_handle), &status).await;
+        ItemChange::Icon(icon)
+    }
+
+    fn parse_status(status: &str) -> TrayStatus {
+        match status {
+            "Active" => TrayStatus::Active,
+            "Passive" => TrayStatus::Passive,
+            "NeedsAttention" => TrayStatus::NeedsAttention,
+            _ => TrayStatus::Passive,
+        }
+    }
+
     async fn create_tray_item_from_proxy(
         proxy: &SniItemProxy<'_>,
         service_name: &str,
+        namespace: SniNamespace,
+        scale: f64,
     ) -> Result<TrayItem, Box<dyn std::error::Error>> {
         let id = proxy.id().await.unwrap_or_else(|_| service_name.to_string());
         let title = proxy.title().await.ok();
-        let tooltip = proxy.tool_tip().await.ok();
-        let icon_name = proxy.icon_name().await.ok();
-        
-        let status = match proxy.status().await.unwrap_or_default().as_str() {
-            "Active" => TrayStatus::Active,
-            "Passive" => TrayStatus::Passive,
-            "NeedsAttention" => TrayStatus::NeedsAttention,
-            _ => TrayStatus::Passive,
-        };
+        let tooltip = proxy.tool_tip().await.ok().and_then(TrayTooltip::from_sni);
+        let status = Self::parse_status(&proxy.status().await.unwrap_or_default());
 
         let category = match proxy.category().await.unwrap_or_default().as_str() {
             "ApplicationStatus" => TrayCategory::ApplicationStatus,
@@ -154,76 +436,132 @@ impl SniWatcher {
             _ => TrayCategory::ApplicationStatus,
         };
 
-        let icon_data = Self::get_icon_data(proxy).await;
-        let menu_path = proxy.menu().await.ok();
+        let icon = Self::get_item_icon(proxy, service_name, scale, &status).await;
+        let menu_path = proxy.menu().await.ok().map(|path| path.to_string());
 
         Ok(TrayItem {
             id,
             service_name: service_name.to_string(),
-            icon_name,
-            icon_data,
+            icon_name: icon.icon_name,
+            icon_data: icon.icon_data,
+            attention_frames: icon.attention_frames,
             title,
             tooltip,
             status,
             category,
             menu_path,
+            namespace,
+            registered_name: None,
+            signal_watcher: None,
+            sequence: next_sequence(),
         })
     }
 
-    async fn get_icon_data(proxy: &SniItemProxy<'_>) -> Option<String> {
-        // Try to get icon pixmap first
-        if let Ok(pixmaps) = proxy.icon_pixmap().await {
-            if let Some(pixmap) = pixmaps.first() {
-                if let Ok(base64_data) = Self::convert_pixmap_to_base64(pixmap) {
-                    return Some(base64_data);
-                }
-            }
+    async fn get_item_icon(
+        proxy: &SniItemProxy<'_>,
+        service_name: &str,
+        scale: f64,
+        status: &TrayStatus,
+    ) -> ItemIcon {
+        let icon_theme_path = proxy.icon_theme_path().await.unwrap_or_default();
+        let extra_dirs: Vec<PathBuf> = Some(icon_theme_path)
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from)
+            .into_iter()
+            .collect();
+
+        ItemIcon {
+            icon_name: proxy.icon_name().await.ok(),
+            icon_data: Self::get_icon_data(proxy, service_name, scale, status, &extra_dirs).await,
+            attention_frames: Self::get_attention_frames(proxy, scale, &extra_dirs).await,
+        }
+    }
+
+    /// Renders the item icon at `TRAY_ICON_SIZE` for the given HiDPI scale,
+    /// reusing the cached result until the item signals a new icon or status.
+    async fn get_icon_data(
+        proxy: &SniItemProxy<'_>,
+        service_name: &str,
+        scale: f64,
+        status: &TrayStatus,
+        extra_dirs: &[PathBuf],
+    ) -> Option<String> {
+        if let Some(icon_data) = pixmap::cached_icon(service_name, TRAY_ICON_SIZE, scale) {
+            return Some(icon_data);
+        }
+
+        let mut image = None;
+        if matches!(status, TrayStatus::NeedsAttention) {
+            image = Self::read_icon_image(proxy, IconKind::Attention, scale, extra_dirs).await;
+        }
+        if image.is_none() {
+            image = Self::read_icon_image(proxy, IconKind::Normal, scale, extra_dirs).await;
         }
+        let mut image = image?;
 
-        // Fallback to icon theme lookup if icon_name is available
-        if let Ok(icon_name) = proxy.icon_name().await {
-            return Self::get_icon_from_theme(&icon_name).await;
+        if let Some(overlay) = Self::read_icon_image(proxy, IconKind::Overlay, scale, extra_dirs).await {
+            pixmap::composite_overlay(&mut image, &overlay);
         }
 
-        None
+        let icon_data = pixmap::encode_png_base64(&image).ok()?;
+        pixmap::cache_icon(service_name, TRAY_ICON_SIZE, scale, icon_data.clone());
+        Some(icon_data)
     }
 
-    fn convert_pixmap_to_base64(pixmap: &(i32, i32, Vec<u8>)) -> Result<String, Box<dyn std::error::Error>> {
-        let (width, height, data) = pixmap;
-        
-        // Convert ARGB to RGBA
-        let mut rgba_data = Vec::with_capacity(data.len());
-        for chunk in data.chunks(4) {
-            if chunk.len() == 4 {
-                rgba_data.extend_from_slice(&[chunk[2], chunk[1], chunk[0], chunk[3]]);
-            }
+    /// Reads one of the item's icons, preferring its pixmaps over the theme.
+    async fn read_icon_image(
+        proxy: &SniItemProxy<'_>,
+        kind: IconKind,
+        scale: f64,
+        extra_dirs: &[PathBuf],
+    ) -> Option<RgbaImage> {
+        let target = (TRAY_ICON_SIZE as f64 * scale).round() as u32;
+
+        let pixmaps = match kind {
+            IconKind::Normal => proxy.icon_pixmap().await,
+            IconKind::Attention => proxy.attention_icon_pixmap().await,
+            IconKind::Overlay => proxy.overlay_icon_pixmap().await,
+        };
+        if let Some(image) = pixmaps.ok().and_then(|pixmaps| pixmap::render_image(&pixmaps, target)) {
+            return Some(image);
         }
 
-        let img = image::RgbaImage::from_raw(*width as u32, *height as u32, rgba_data)
-            .ok_or("Failed to create image")?;
-        
-        let mut buffer = Vec::new();
-        img.write_to(&mut std::io::Cursor::new(&mut buffer), image::ImageFormat::Png)?;
-        
-        Ok(general_purpose::STANDARD.encode(&buffer))
+        let icon_name = match kind {
+            IconKind::Normal => proxy.icon_name().await,
+            IconKind::Attention => proxy.attention_icon_name().await,
+            IconKind::Overlay => proxy.overlay_icon_name().await,
+        };
+        Self::get_icon_from_theme(&icon_name.ok()?, extra_dirs, scale.ceil() as u32).await
     }
 
-    async fn get_icon_from_theme(icon_name: &str) -> Option<String> {
-        // Simple icon theme lookup - you might want to use a proper icon theme library
-        let common_paths = [
-            format!("/usr/share/icons/hicolor/16x16/apps/{}.png", icon_name),
-            format!("/usr/share/icons/hicolor/22x22/apps/{}.png", icon_name),
-            format!("/usr/share/icons/hicolor/24x24/apps/{}.png", icon_name),
-            format!("/usr/share/pixmaps/{}.png", icon_name),
-        ];
+    async fn get_icon_from_theme(icon_name: &str, extra_dirs: &[PathBuf], scale: u32) -> Option<RgbaImage> {
+        let icon_name = icon_name.to_string();
+        let extra_dirs = extra_dirs.to_vec();
 
-        for path in &common_paths {
-            if let Ok(data) = tokio::fs::read(path).await {
-                return Some(general_purpose::STANDARD.encode(&data));
-            }
-        }
+        tokio::task::spawn_blocking(move || {
+            icon_theme::lookup_icon_image(&icon_name, TRAY_ICON_SIZE, scale.max(1), &extra_dirs)
+        })
+        .await
+        .ok()
+        .flatten()
+    }
 
-        None
+    async fn get_attention_frames(
+        proxy: &SniItemProxy<'_>,
+        scale: f64,
+        extra_dirs: &[PathBuf],
+    ) -> Option<Vec<TrayIconFrame>> {
+        let movie_name = proxy.attention_movie_name().await.ok().filter(|name| !name.is_empty())?;
+        let target = (TRAY_ICON_SIZE as f64 * scale).round() as u32;
+        let extra_dirs = extra_dirs.to_vec();
+
+        tokio::task::spawn_blocking(move || {
+            let path = IconTheme::current().lookup_movie(&movie_name, &extra_dirs)?;
+            pixmap::load_movie_frames(&path, target)
+        })
+        .await
+        .ok()
+        .flatten()
     }
 
     async fn discover_existing_items(&self) -> Result<(), Box<dyn std::error::Error>> {
@@ -238,8 +576,8 @@ impl SniWatcher {
         let names: Vec<String> = proxy.call("ListNames", &()).await?;
         
         for name in names {
-            if name.starts_with("org.kde.StatusNotifierItem") {
-                if let Err(e) = Self::register_item(&self.connection, &self.tray_manager, &self.app_handle, &name).await {
+            if SniNamespace::from_item_service(&name).is_some() {
+                if let Err(e) = Self::register_item(&self.connection, &self.tray_manager, &self.menu_cache, &self.app_handle, &name, None).await {
                     eprintln!("[SNI] Error registrando item existente {}: {}", name, e);
                 }
             }
@@ -248,3 +586,178 @@ impl SniWatcher {
         Ok(())
     }
 }
+
+/// State of the tray subsystem, as reported by `tray_status`.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(tag = "state", content = "message", rename_all = "kebab-case")]
+pub enum SniWatcherStatus {
+    #[default]
+    Stopped,
+    /// Connecting to the bus and taking the names
+    Starting,
+    /// vpanel owns the watcher name
+    Watcher,
+    /// Another process owns the watcher name and vpanel is a host
+    Host,
+    Failed(String),
+}
+
+/// The running watcher together with the tasks it spawned.
+#[derive(Default)]
+pub struct SniWatcherState {
+    pub status: SniWatcherStatus,
+    watcher: Option<SniWatcher>,
+    tasks: Vec<AbortHandle>,
+}
+
+pub type SniWatcherHandle = Arc<Mutex<SniWatcherState>>;
+
+pub fn create_sni_watcher_handle() -> SniWatcherHandle {
+    Arc::new(Mutex::new(SniWatcherState::default()))
+}
+
+/// Starts the watcher unless it is already running or starting, and returns
+/// its status. A watcher that failed to start is tried again. The handle is
+/// only locked to check and store the state, so `tray_status` never waits
+/// for the bus.
+pub async fn start_sni_watcher(
+    handle: &SniWatcherHandle,
+    tray_manager: TrayManager,
+    menu_cache: TrayMenuCache,
+    app_handle: AppHandle,
+) -> SniWatcherStatus {
+    {
+        let mut state = handle.lock().await;
+        if matches!(
+            state.status,
+            SniWatcherStatus::Starting | SniWatcherStatus::Watcher | SniWatcherStatus::Host
+        ) {
+            return state.status.clone();
+        }
+        state.status = SniWatcherStatus::Starting;
+    }
+
+    let watcher = SniWatcher::new(tray_manager, menu_cache, app_handle.clone())
+        .await
+        .map_err(|e| format!("Error inicializando SNI watcher: {}", e));
+    let started = match watcher {
+        Ok(watcher) => {
+            let tasks = watcher
+                .start_watching()
+                .await
+                .map_err(|e| format!("Error iniciando watcher: {}", e));
+            match tasks {
+                Ok(tasks) => Ok((watcher, tasks)),
+                Err(message) => {
+                    watcher.release_names().await;
+                    Err(message)
+                }
+            }
+        }
+        Err(message) => Err(message),
+    };
+
+    let (watcher, tasks) = match started {
+        Ok(started) => started,
+        Err(message) => {
+            eprintln!("[SNI] {}", message);
+            let mut state = handle.lock().await;
+            // A stop while starting wins over the failure
+            if matches!(state.status, SniWatcherStatus::Starting) {
+                state.status = SniWatcherStatus::Failed(message);
+            }
+            return state.status.clone();
+        }
+    };
+
+    let status = match watcher.mode() {
+        SniMode::Watcher => SniWatcherStatus::Watcher,
+        SniMode::Host => SniWatcherStatus::Host,
+    };
+
+    {
+        let mut state = handle.lock().await;
+        if !matches!(state.status, SniWatcherStatus::Starting) {
+            // Stopped while starting: undo instead of storing
+            drop(state);
+            for task in tasks {
+                task.abort();
+            }
+            watcher.release_names().await;
+            return SniWatcherStatus::Stopped;
+        }
+
+        state.status = status.clone();
+        state.watcher = Some(watcher);
+        state.tasks = tasks;
+    }
+
+    // The panel's own item needs a watcher to register with
+    if let Err(e) = panel_item::start_panel_item(app_handle).await {
+        eprintln!("[Tray] Error iniciando el item del panel: {}", e);
+    }
+
+    status
+}
+
+/// Cancels the watcher's tasks and gives up its bus names. A watcher still
+/// starting is undone by `start_sni_watcher` once it sees the new status.
+pub async fn stop_sni_watcher(handle: &SniWatcherHandle) {
+    let (watcher, tasks) = {
+        let mut state = handle.lock().await;
+        state.status = SniWatcherStatus::Stopped;
+        (state.watcher.take(), std::mem::take(&mut state.tasks))
+    };
+
+    for task in tasks {
+        task.abort();
+    }
+    if let Some(watcher) = watcher {
+        watcher.release_names().await;
+    }
+}
+
+#[derive(Clone, Copy)]
+enum IconKind {
+    Normal,
+    Attention,
+    Overlay,
+}
+
+/// Icon fields of a `TrayItem`, re-read together since status and overlay
+/// both affect the rendered icon.
+struct ItemIcon {
+    icon_name: Option<String>,
+    icon_data: Option<String>,
+    attention_frames: Option<Vec<TrayIconFrame>>,
+}
+
+impl ItemIcon {
+    fn apply(self, item: &mut TrayItem) {
+        item.icon_name = self.icon_name;
+        item.icon_data = self.icon_data;
+        item.attention_frames = self.attention_frames;
+    }
+}
+
+/// A property re-read after one of the item's `New*` signals.
+enum ItemChange {
+    Title(Option<String>),
+    Icon(ItemIcon),
+    ToolTip(Option<TrayTooltip>),
+    Status(TrayStatus, ItemIcon),
+}
+
+impl ItemChange {
+    fn apply(self, item: &mut TrayItem) {
+        match self {
+            ItemChange::Title(title) => item.title = title,
+            ItemChange::Icon(icon) => icon.apply(item),
+            ItemChange::ToolTip(tooltip) => item.tooltip = tooltip,
+            ItemChange::Status(status, icon) => {
+                item.status = status;
+                icon.apply(item);
+            }
+        }
+    }
+}
@@ -0,0 +1,199 @@
+use super::{TrayManager, TrayMenuCache};
+use crate::tray::sni_host;
+use crate::tray::sni_watcher::SniWatcher;
+use tauri::AppHandle;
+use zbus::{interface, message::Header, object_server::{Interface, InterfaceRef}, Connection, SignalContext};
+
+pub const SNI_WATCHER_PATH: &str = "/StatusNotifierWatcher";
+
+/// Defines a watcher interface type. KDE and freedesktop clients use the same
+/// protocol under different interface names, so both are generated from here.
+macro_rules! status_notifier_watcher {
+    ($(#[$doc:meta])* $watcher:ident, $interface:tt) => {
+        $(#[$doc])*
+        ///
+        /// Items are registered into the shared `TrayManager`. vpanel displays
+        /// them itself, so its own host name is the first registered host.
+        pub struct $watcher {
+            tray_manager: TrayManager,
+            menu_cache: TrayMenuCache,
+            app_handle: AppHandle,
+            hosts: Vec<String>,
+        }
+
+        impl $watcher {
+            pub fn new(tray_manager: TrayManager, menu_cache: TrayMenuCache, app_handle: AppHandle) -> Self {
+                Self {
+                    tray_manager,
+                    menu_cache,
+                    app_handle,
+                    hosts: vec![sni_host::host_name()],
+                }
+            }
+
+            /// Forgets a host whose bus name vanished, returning whether it was known.
+            pub fn remove_host(&mut self, service: &str) -> bool {
+                let before = self.hosts.len();
+                self.hosts.retain(|host| host != service);
+                self.hosts.len() != before
+            }
+
+            async fn emit_item_registered(connection: &Connection, service: &str) -> zbus::Result<()> {
+                let Some(iface_ref) = get_interface::<Self>(connection).await? else {
+                    return Ok(());
+                };
+                let ctxt = iface_ref.signal_context();
+
+                Self::status_notifier_item_registered(ctxt, service).await?;
+                let iface = iface_ref.get().await;
+                iface.registered_status_notifier_items_changed(ctxt).await
+            }
+
+            async fn emit_item_unregistered(connection: &Connection, service: &str) -> zbus::Result<()> {
+                let Some(iface_ref) = get_interface::<Self>(connection).await? else {
+                    return Ok(());
+                };
+                let ctxt = iface_ref.signal_context();
+
+                Self::status_notifier_item_unregistered(ctxt, service).await?;
+                let iface = iface_ref.get().await;
+                iface.registered_status_notifier_items_changed(ctxt).await
+            }
+
+            async fn remove_host_from(connection: &Connection, service: &str) -> zbus::Result<()> {
+                let Some(iface_ref) = get_interface::<Self>(connection).await? else {
+                    return Ok(());
+                };
+
+                if iface_ref.get_mut().await.remove_host(service) {
+                    println!("[SNI] Desregistrando host: {}", service);
+                    Self::status_notifier_host_unregistered(iface_ref.signal_context()).await?;
+                }
+
+                Ok(())
+            }
+        }
+
+        #[interface(name = $interface)]
+        impl $watcher {
+            async fn register_status_notifier_item(
+                &self,
+                service: String,
+                #[zbus(header)] header: Header<'_>,
+                #[zbus(connection)] connection: &Connection,
+            ) {
+                let sender = header.sender().map(|sender| sender.to_string());
+                let connection = connection.clone();
+                let tray_manager = self.tray_manager.clone();
+                let menu_cache = self.menu_cache.clone();
+                let app_handle = self.app_handle.clone();
+
+                // Reply right away: reading the item's properties calls back into the
+                // client, which may be blocked waiting for this reply
+                // zbus runs handlers on its own executor, without a tokio reactor
+                tauri::async_runtime::spawn(async move {
+                    let registered = SniWatcher::register_item(
+                        &connection,
+                        &tray_manager,
+                        &menu_cache,
+                        &app_handle,
+                        &service,
+                        sender.as_deref(),
+                    )
+                    .await
+                    .map_err(|e| e.to_string());
+
+                    match registered {
+                        Ok(service_name) => {
+                            if let Err(e) = emit_item_registered(&connection, &service_name).await {
+                                eprintln!("[SNI] Error emitiendo StatusNotifierItemRegistered: {}", e);
+                            }
+                        }
+                        Err(e) => {
+                            eprintln!("[SNI] Error registrando item {}: {}", service, e);
+                        }
+                    }
+                });
+            }
+
+            async fn register_status_notifier_host(
+                &mut self,
+                service: String,
+                #[zbus(signal_context)] ctxt: SignalContext<'_>,
+            ) -> zbus::fdo::Result<()> {
+                if !self.hosts.contains(&service) {
+                    println!("[SNI] Registrando host: {}", service);
+                    self.hosts.push(service);
+                    Self::status_notifier_host_registered(&ctxt).await?;
+                }
+
+                Ok(())
+            }
+
+            #[zbus(property)]
+            async fn registered_status_notifier_items(&self) -> Vec<String> {
+                self.tray_manager.read().await.keys().cloned().collect()
+            }
+
+            #[zbus(property)]
+            async fn is_status_notifier_host_registered(&self) -> bool {
+                !self.hosts.is_empty()
+            }
+
+            #[zbus(property)]
+            async fn protocol_version(&self) -> i32 {
+                0
+            }
+
+            #[zbus(signal)]
+            async fn status_notifier_item_registered(ctxt: &SignalContext<'_>, service: &str) -> zbus::Result<()>;
+
+            #[zbus(signal)]
+            async fn status_notifier_item_unregistered(ctxt: &SignalContext<'_>, service: &str) -> zbus::Result<()>;
+
+            #[zbus(signal)]
+            async fn status_notifier_host_registered(ctxt: &SignalContext<'_>) -> zbus::Result<()>;
+
+            #[zbus(signal)]
+            async fn status_notifier_host_unregistered(ctxt: &SignalContext<'_>) -> zbus::Result<()>;
+        }
+    };
+}
+
+status_notifier_watcher!(
+    /// `org.kde.StatusNotifierWatcher` served at `/StatusNotifierWatcher`.
+    StatusNotifierWatcher,
+    "org.kde.StatusNotifierWatcher"
+);
+
+status_notifier_watcher!(
+    /// `org.freedesktop.StatusNotifierWatcher` served at `/StatusNotifierWatcher`.
+    FreedesktopStatusNotifierWatcher,
+    "org.freedesktop.StatusNotifierWatcher"
+);
+
+/// Returns a served watcher interface, or `None` when it is not being served
+/// (host mode, or its name is owned by another watcher).
+async fn get_interface<I: Interface>(connection: &Connection) -> zbus::Result<Option<InterfaceRef<I>>> {
+    match connection.object_server().interface::<_, I>(SNI_WATCHER_PATH).await {
+        Ok(iface_ref) => Ok(Some(iface_ref)),
+        Err(zbus::Error::InterfaceNotFound) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+pub async fn emit_item_registered(connection: &Connection, service: &str) -> zbus::Result<()> {
+    StatusNotifierWatcher::emit_item_registered(connection, service).await?;
+    FreedesktopStatusNotifierWatcher::emit_item_registered(connection, service).await
+}
+
+pub async fn emit_item_unregistered(connection: &Connection, service: &str) -> zbus::Result<()> {
+    StatusNotifierWatcher::emit_item_unregistered(connection, service).await?;
+    FreedesktopStatusNotifierWatcher::emit_item_unregistered(connection, service).await
+}
+
+/// Drops a host whose bus name vanished and notifies if it was registered.
+pub async fn remove_host(connection: &Connection, service: &str) -> zbus::Result<()> {
+    StatusNotifierWatcher::remove_host_from(connection, service).await?;
+    FreedesktopStatusNotifierWatcher::remove_host_from(connection, service).await
+}
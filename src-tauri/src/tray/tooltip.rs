@@ -0,0 +1,116 @@
+use super::TrayTooltip;
+use crate::tray::pixmap::{self, SniPixmap};
+
+/// Raw `ToolTip` property: icon name, icon pixmaps, title and body.
+pub type SniToolTip = (String, Vec<SniPixmap>, String, String);
+
+const TOOLTIP_ICON_SIZE: u32 = 48;
+
+impl TrayTooltip {
+    pub fn from_sni(tool_tip: SniToolTip) -> Option<Self> {
+        let (icon_name, icon_pixmaps, title, body) = tool_tip;
+
+        if title.is_empty() && body.is_empty() {
+            return None;
+        }
+
+        let icon = pixmap::render_pixmaps(&icon_pixmaps, TOOLTIP_ICON_SIZE)
+            .map(|data| format!("data:image/png;base64,{}", data))
+            .or_else(|| Some(icon_name).filter(|name| !name.is_empty()));
+
+        let body_is_markup = is_markup(&body);
+        let body_text = if body_is_markup {
+            markup_to_plain_text(&body)
+        } else {
+            body.clone()
+        };
+
+        Some(Self {
+            icon,
+            title,
+            body,
+            body_is_markup,
+            body_text,
+        })
+    }
+}
+
+/// The spec allows a small HTML subset in the body (`<b>`, `<i>`, `<br/>`,
+/// `<a>`, ...); anything with a tag or an entity is treated as markup.
+fn is_markup(text: &str) -> bool {
+    let has_tag = text
+        .find('<')
+        .is_some_and(|start| text[start..].contains('>'));
+    let has_entity = text
+        .find('&')
+        .is_some_and(|start| text[start..].contains(';'));
+
+    has_tag || has_entity
+}
+
+/// Strips tags and decodes entities, turning line-breaking tags into newlines.
+fn markup_to_plain_text(markup: &str) -> String {
+    let mut text = String::with_capacity(markup.len());
+    let mut rest = markup;
+
+    while let Some(start) = rest.find(['<', '&']) {
+        text.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        if rest.starts_with('<') {
+            let Some(end) = rest.find('>') else {
+                text.push_str(rest);
+                rest = "";
+                break;
+            };
+
+            let tag = rest[1..end]
+                .trim_start_matches('/')
+                .split(|c: char| c.is_whitespace() || c == '/')
+                .next()
+                .unwrap_or_default()
+                .to_lowercase();
+            if matches!(tag.as_str(), "br" | "p" | "div" | "li") && !text.ends_with('\n') && !text.is_empty() {
+                text.push('\n');
+            }
+
+            rest = &rest[end + 1..];
+        } else {
+            match rest.find(';').filter(|&end| end <= 10) {
+                Some(end) => {
+                    match decode_entity(&rest[1..end]) {
+                        Some(c) => text.push(c),
+                        None => text.push_str(&rest[..=end]),
+                    }
+                    rest = &rest[end + 1..];
+                }
+                None => {
+                    text.push('&');
+                    rest = &rest[1..];
+                }
+            }
+        }
+    }
+
+    text.push_str(rest);
+    text.trim().to_string()
+}
+
+fn decode_entity(entity: &str) -> Option<char> {
+    match entity {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        "nbsp" => Some(' '),
+        _ => {
+            let code = entity.strip_prefix('#')?;
+            let code = match code.strip_prefix(['x', 'X']) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => code.parse().ok()?,
+            };
+            char::from_u32(code)
+        }
+    }
+}
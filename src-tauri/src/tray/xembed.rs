@@ -0,0 +1,710 @@
+use super::error::TrayError;
+use super::{emit_tray_item_changed, emit_tray_update, next_sequence, pixmap, TrayCategory, TrayItem, TrayManager, TrayStatus};
+use image::RgbaImage;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tauri::AppHandle;
+use x11rb::connection::Connection;
+use x11rb::protocol::composite::{self, ConnectionExt as _};
+use x11rb::protocol::damage::{self, ConnectionExt as _};
+use x11rb::protocol::shape;
+use x11rb::protocol::xfixes::ConnectionExt as _;
+use x11rb::protocol::xproto::*;
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+use x11rb::wrapper::ConnectionExt as _;
+use x11rb::{CURRENT_TIME, NONE};
+
+/// Prefix of the `TrayItem::service_name` of embedded icons, followed by the
+/// icon's X window id.
+pub const XEMBED_PREFIX: &str = "xembed:";
+
+const ICON_SIZE: u16 = 24;
+const SYSTEM_TRAY_REQUEST_DOCK: u32 = 0;
+const XEMBED_EMBEDDED_NOTIFY: u32 = 0;
+/// Where containers sit while not being clicked, outside every screen
+const HIDDEN_POSITION: i16 = -2 * ICON_SIZE as i16;
+const CLICK_HIDE_DELAY: Duration = Duration::from_millis(500);
+
+/// The running tray, cleared when it loses the selection so it can start again.
+static TRAY: Mutex<Option<Arc<XembedTray>>> = Mutex::new(None);
+
+/// An icon reparented into its own offscreen container.
+struct EmbeddedIcon {
+    container: Window,
+    damage: damage::Damage,
+}
+
+/// Owner of the `_NET_SYSTEM_TRAY_S<n>` selection. Icons are kept in
+/// composite-redirected containers that never reach the screen; their
+/// contents are captured on every damage and shown as regular tray items.
+pub struct XembedTray {
+    conn: RustConnection,
+    root: Window,
+    owner: Window,
+    atoms: HashMap<&'static str, Atom>,
+    icons: Mutex<HashMap<Window, EmbeddedIcon>>,
+}
+
+pub fn is_xembed_item(service_name: &str) -> bool {
+    service_name.starts_with(XEMBED_PREFIX)
+}
+
+/// Takes the system tray selection and starts embedding icons. Returns
+/// `false` when another tray already owns the selection.
+pub fn start_xembed_tray(tray_manager: TrayManager, app_handle: AppHandle) -> Result<bool, Box<dyn std::error::Error>> {
+    let mut current = TRAY.lock().unwrap_or_else(|e| e.into_inner());
+    if current.is_some() {
+        return Ok(true);
+    }
+
+    let (conn, screen_num) = x11rb::connect(None)?;
+    let Some(tray) = XembedTray::acquire(conn, screen_num)? else {
+        return Ok(false);
+    };
+
+    let tray = Arc::new(tray);
+    *current = Some(tray.clone());
+
+    thread::spawn(move || tray.run(tray_manager, app_handle));
+
+    Ok(true)
+}
+
+/// Sends a synthetic button press and release to an embedded icon.
+pub fn send_click(service_name: &str, button: u8, x: i32, y: i32) -> Result<(), TrayError> {
+    let tray = TRAY
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone()
+        .ok_or_else(|| TrayError::ProtocolUnavailable("La bandeja XEmbed no está iniciada".to_string()))?;
+    let icon = parse_icon_window(service_name).map_err(|e| TrayError::InvalidArgument(e.to_string()))?;
+    XembedTray::click(&tray, icon, button, x, y)
+}
+
+fn button_mask(button: u8) -> KeyButMask {
+    match button {
+        1 => KeyButMask::BUTTON1,
+        2 => KeyButMask::BUTTON2,
+        3 => KeyButMask::BUTTON3,
+        4 => KeyButMask::BUTTON4,
+        5 => KeyButMask::BUTTON5,
+        _ => KeyButMask::default(),
+    }
+}
+
+fn parse_icon_window(service_name: &str) -> Result<Window, Box<dyn std::error::Error>> {
+    let id = service_name
+        .strip_prefix(XEMBED_PREFIX)
+        .ok_or_else(|| format!("{} no es un icono XEmbed", service_name))?;
+    Ok(id.parse()?)
+}
+
+impl XembedTray {
+    /// Takes the system tray selection of `screen_num` on `conn`, announcing
+    /// the new tray. Returns `None` when another tray already owns it.
+    fn acquire(conn: RustConnection, screen_num: usize) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        let root = conn.setup().roots[screen_num].root;
+
+        // Icons are captured from offscreen storage, which needs both extensions
+        conn.composite_query_version(0, 4)?.reply()?;
+        conn.damage_query_version(1, 1)?.reply()?;
+        // Containers get an empty input region so they never take clicks
+        conn.xfixes_query_version(5, 0)?.reply()?;
+
+        let selection_name = format!("_NET_SYSTEM_TRAY_S{}", screen_num);
+        let atom_names = [
+            "_NET_SYSTEM_TRAY_OPCODE",
+            "_NET_SYSTEM_TRAY_ORIENTATION",
+            "_NET_WM_NAME",
+            "UTF8_STRING",
+            "MANAGER",
+            "_XEMBED",
+        ];
+
+        let mut atoms = HashMap::new();
+        for name in atom_names.iter() {
+            let interned_atom = conn.intern_atom(false, name.as_bytes())?.reply()?;
+            atoms.insert(*name, interned_atom.atom);
+        }
+        let selection = conn.intern_atom(false, selection_name.as_bytes())?.reply()?.atom;
+
+        let owner = conn.generate_id()?;
+        conn.create_window(
+            x11rb::COPY_DEPTH_FROM_PARENT,
+            owner,
+            root,
+            -1,
+            -1,
+            1,
+            1,
+            0,
+            WindowClass::INPUT_OUTPUT,
+            x11rb::COPY_FROM_PARENT,
+            &CreateWindowAux::new().override_redirect(1),
+        )?;
+
+        conn.set_selection_owner(owner, selection, CURRENT_TIME)?;
+        if conn.get_selection_owner(selection)?.reply()?.owner != owner {
+            println!("[XEmbed] {} ya tiene dueño, no se inicia la bandeja XEmbed", selection_name);
+            conn.destroy_window(owner)?;
+            conn.flush()?;
+            return Ok(None);
+        }
+
+        // Horizontal orientation
+        conn.change_property32(
+            PropMode::REPLACE,
+            owner,
+            atoms["_NET_SYSTEM_TRAY_ORIENTATION"],
+            AtomEnum::CARDINAL,
+            &[0],
+        )?;
+
+        // Announce the new tray so running clients dock their icons
+        let manager_event = ClientMessageEvent::new(32, root, atoms["MANAGER"], [CURRENT_TIME, selection, owner, 0, 0]);
+        conn.send_event(false, root, EventMask::STRUCTURE_NOTIFY, manager_event)?;
+        conn.flush()?;
+
+        println!("[XEmbed] Bandeja XEmbed iniciada en {}", selection_name);
+
+        Ok(Some(XembedTray {
+            conn,
+            root,
+            owner,
+            atoms,
+            icons: Mutex::new(HashMap::new()),
+        }))
+    }
+
+    fn run(&self, tray_manager: TrayManager, app_handle: AppHandle) {
+        loop {
+            let event = match self.conn.wait_for_event() {
+                Ok(event) => event,
+                Err(e) => {
+                    eprintln!("[XEmbed] Error leyendo eventos de X11: {}", e);
+                    break;
+                }
+            };
+
+            match event {
+                Event::ClientMessage(event) if event.type_ == self.atoms["_NET_SYSTEM_TRAY_OPCODE"] => {
+                    let data = event.data.as_data32();
+                    if data[1] == SYSTEM_TRAY_REQUEST_DOCK {
+                        self.dock(data[2], &tray_manager, &app_handle);
+                    }
+                }
+                Event::DamageNotify(event) => {
+                    let _ = self.conn.damage_subtract(event.damage, NONE, NONE);
+                    self.update_icon(event.drawable, &tray_manager, &app_handle);
+                }
+                Event::PropertyNotify(event)
+                    if event.atom == self.atoms["_NET_WM_NAME"] || event.atom == u32::from(AtomEnum::WM_NAME) =>
+                {
+                    self.update_title(event.window, &tray_manager, &app_handle);
+                }
+                Event::DestroyNotify(event) => self.undock(event.window, &tray_manager, &app_handle),
+                Event::ReparentNotify(event) => {
+                    // The client pulled its icon out of our container
+                    let container = self.icons.lock().ok().and_then(|icons| icons.get(&event.window).map(|icon| icon.container));
+                    if container.is_some_and(|container| container != event.parent) {
+                        self.undock(event.window, &tray_manager, &app_handle);
+                    }
+                }
+                Event::SelectionClear(event) if event.owner == self.owner => {
+                    println!("[XEmbed] Otra bandeja tomó la selección del system tray");
+                    break;
+                }
+                _ => {}
+            }
+        }
+
+        self.stop(&tray_manager, &app_handle);
+    }
+
+    /// Forgets this tray and hands every icon back to the root window, so
+    /// the next tray can embed it, dropping their items from the panel.
+    fn stop(&self, tray_manager: &TrayManager, app_handle: &AppHandle) {
+        {
+            let mut current = TRAY.lock().unwrap_or_else(|e| e.into_inner());
+            if current.as_deref().is_some_and(|tray| std::ptr::eq(tray, self)) {
+                *current = None;
+            }
+        }
+
+        let icons: Vec<(Window, EmbeddedIcon)> = self
+            .icons
+            .lock()
+            .map(|mut icons| icons.drain().collect())
+            .unwrap_or_default();
+        for (icon, embedded) in icons {
+            let _ = self.conn.damage_destroy(embedded.damage);
+            let _ = self.conn.unmap_window(icon);
+            let _ = self.conn.reparent_window(icon, self.root, 0, 0);
+            let _ = self.conn.destroy_window(embedded.container);
+        }
+        let _ = self.conn.destroy_window(self.owner);
+        let _ = self.conn.flush();
+
+        tray_manager.blocking_write().retain(|service_name, _| !is_xembed_item(service_name));
+        tauri::async_runtime::block_on(emit_tray_update(app_handle));
+        println!("[XEmbed] Bandeja XEmbed detenida");
+    }
+
+    /// Clicks `icon` with its container moved under the pointer, so menus
+    /// open at the right place, and hides the container again afterwards.
+    fn click(tray: &Arc<Self>, icon: Window, button: u8, x: i32, y: i32) -> Result<(), TrayError> {
+        let container = tray
+            .container(icon)
+            .ok_or_else(|| TrayError::ItemNotFound(format!("Icono XEmbed {} no encontrado", icon)))?;
+
+        tray.conn
+            .configure_window(container, &ConfigureWindowAux::new().x(x).y(y))?;
+
+        let center = (ICON_SIZE / 2) as i16;
+        let mut event = ButtonPressEvent {
+            response_type: BUTTON_PRESS_EVENT,
+            detail: button,
+            sequence: 0,
+            time: CURRENT_TIME,
+            root: tray.root,
+            event: icon,
+            child: NONE,
+            root_x: x as i16,
+            root_y: y as i16,
+            event_x: center,
+            event_y: center,
+            state: KeyButMask::default(),
+            same_screen: true,
+        };
+        tray.conn.send_event(false, icon, EventMask::BUTTON_PRESS, event)?;
+
+        event.response_type = BUTTON_RELEASE_EVENT;
+        event.state = button_mask(button);
+        tray.conn.send_event(false, icon, EventMask::BUTTON_RELEASE, event)?;
+
+        tray.conn.flush()?;
+
+        // The client reads the container position when it handles the
+        // press, so it is hidden again only after a while
+        let tray = tray.clone();
+        thread::spawn(move || {
+            thread::sleep(CLICK_HIDE_DELAY);
+            tray.hide_container(icon);
+        });
+
+        Ok(())
+    }
+
+    fn container(&self, icon: Window) -> Option<Window> {
+        self.icons.lock().ok()?.get(&icon).map(|embedded| embedded.container)
+    }
+
+    fn hide_container(&self, icon: Window) {
+        if let Some(container) = self.container(icon) {
+            let aux = ConfigureWindowAux::new().x(i32::from(HIDDEN_POSITION)).y(i32::from(HIDDEN_POSITION));
+            let _ = self.conn.configure_window(container, &aux);
+            let _ = self.conn.flush();
+        }
+    }
+
+    fn dock(&self, icon: Window, tray_manager: &TrayManager, app_handle: &AppHandle) {
+        if self.icons.lock().map(|icons| icons.contains_key(&icon)).unwrap_or(true) {
+            return;
+        }
+
+        println!("[XEmbed] Incrustando icono: {}", icon);
+
+        let embedded = match self.embed(icon) {
+            Ok(embedded) => embedded,
+            Err(e) => {
+                eprintln!("[XEmbed] Error incrustando icono {}: {}", icon, e);
+                return;
+            }
+        };
+
+        if let Ok(mut icons) = self.icons.lock() {
+            icons.insert(icon, embedded);
+        }
+
+        let service_name = format!("{}{}", XEMBED_PREFIX, icon);
+        let class_name = self.get_window_class(icon);
+        let title = self.get_window_title(icon).filter(|title| !title.is_empty());
+
+        let item = TrayItem {
+            id: class_name.clone().unwrap_or_else(|| service_name.clone()),
+            service_name: service_name.clone(),
+            icon_name: class_name.map(|class_name| class_name.to_lowercase()),
+            icon_data: None,
+            attention_frames: None,
+            title,
+            tooltip: None,
+            status: TrayStatus::Active,
+            category: TrayCategory::ApplicationStatus,
+            menu_path: None,
+            namespace: Default::default(),
+            registered_name: None,
+            signal_watcher: None,
+            sequence: next_sequence(),
+        };
+
+        tray_manager.blocking_write().insert(service_name, item);
+        tauri::async_runtime::block_on(emit_tray_update(app_handle));
+    }
+
+    fn embed(&self, icon: Window) -> Result<EmbeddedIcon, Box<dyn std::error::Error>> {
+        let container = self.conn.generate_id()?;
+        self.conn.create_window(
+            x11rb::COPY_DEPTH_FROM_PARENT,
+            container,
+            self.root,
+            HIDDEN_POSITION,
+            HIDDEN_POSITION,
+            ICON_SIZE,
+            ICON_SIZE,
+            0,
+            WindowClass::INPUT_OUTPUT,
+            x11rb::COPY_FROM_PARENT,
+            &CreateWindowAux::new().override_redirect(1).background_pixel(0),
+        )?;
+
+        // Manual redirection keeps the container's contents off the screen
+        // while X still renders the icon into storage we can read back. The
+        // window itself would still take pointer input, hence the empty input
+        // region
+        self.conn.composite_redirect_window(container, composite::Redirect::MANUAL)?;
+        let region = self.conn.generate_id()?;
+        self.conn.xfixes_create_region(region, &[])?;
+        self.conn.xfixes_set_window_shape_region(container, shape::SK::INPUT, 0, 0, region)?;
+        self.conn.xfixes_destroy_region(region)?;
+
+        self.conn.change_window_attributes(
+            icon,
+            &ChangeWindowAttributesAux::new().event_mask(EventMask::STRUCTURE_NOTIFY | EventMask::PROPERTY_CHANGE),
+        )?;
+        self.conn.reparent_window(icon, container, 0, 0)?;
+        self.conn.configure_window(
+            icon,
+            &ConfigureWindowAux::new().width(ICON_SIZE as u32).height(ICON_SIZE as u32),
+        )?;
+
+        let notify = ClientMessageEvent::new(
+            32,
+            icon,
+            self.atoms["_XEMBED"],
+            [CURRENT_TIME, XEMBED_EMBEDDED_NOTIFY, 0, container, 0],
+        );
+        self.conn.send_event(false, icon, EventMask::NO_EVENT, notify)?;
+
+        self.conn.map_window(icon)?;
+        self.conn.map_window(container)?;
+
+        let damage = self.conn.generate_id()?;
+        self.conn.damage_create(damage, icon, damage::ReportLevel::NON_EMPTY)?;
+        self.conn.flush()?;
+
+        Ok(EmbeddedIcon { container, damage })
+    }
+
+    fn undock(&self, icon: Window, tray_manager: &TrayManager, app_handle: &AppHandle) {
+        let Some(embedded) = self.icons.lock().ok().and_then(|mut icons| icons.remove(&icon)) else {
+            return;
+        };
+
+        println!("[XEmbed] Quitando icono: {}", icon);
+
+        let _ = self.conn.damage_destroy(embedded.damage);
+        let _ = self.conn.destroy_window(embedded.container);
+        let _ = self.conn.flush();
+
+        tray_manager.blocking_write().remove(&format!("{}{}", XEMBED_PREFIX, icon));
+        tauri::async_runtime::block_on(emit_tray_update(app_handle));
+    }
+
+    fn update_icon(&self, icon: Window, tray_manager: &TrayManager, app_handle: &AppHandle) {
+        let Some(icon_data) = self.capture(icon).and_then(|image| pixmap::encode_png_base64(&image).ok()) else {
+            return;
+        };
+
+        self.update_item(icon, tray_manager, app_handle, |item| item.icon_data = Some(icon_data));
+    }
+
+    fn update_title(&self, icon: Window, tray_manager: &TrayManager, app_handle: &AppHandle) {
+        let title = self.get_window_title(icon).filter(|title| !title.is_empty());
+        self.update_item(icon, tray_manager, app_handle, |item| item.title = title);
+    }
+
+    fn update_item(
+        &self,
+        icon: Window,
+        tray_manager: &TrayManager,
+        app_handle: &AppHandle,
+        update: impl FnOnce(&mut TrayItem),
+    ) {
+        let updated = {
+            let mut manager = tray_manager.blocking_write();
+            manager.get_mut(&format!("{}{}", XEMBED_PREFIX, icon)).map(|item| {
+                update(item);
+                item.clone()
+            })
+        };
+
+        if let Some(item) = updated {
+            tauri::async_runtime::block_on(emit_tray_item_changed(app_handle, &item));
+        }
+    }
+
+    /// Reads the icon's offscreen contents. 24-bit icons are opaque; 32-bit
+    /// ARGB icons come premultiplied.
+    fn capture(&self, icon: Window) -> Option<RgbaImage> {
+        let geometry = self.conn.get_geometry(icon).ok()?.reply().ok()?;
+        let (width, height) = (geometry.width, geometry.height);
+        if width == 0 || height == 0 {
+            return None;
+        }
+
+        let image = self
+            .conn
+            .get_image(ImageFormat::Z_PIXMAP, icon, 0, 0, width, height, !0)
+            .ok()?
+            .reply()
+            .ok()?;
+        if !matches!(image.depth, 24 | 32) || image.data.len() < width as usize * height as usize * 4 {
+            return None;
+        }
+
+        let lsb_first = self.conn.setup().image_byte_order == ImageOrder::LSB_FIRST;
+        let rgba_data = image
+            .data
+            .chunks_exact(4)
+            .take(width as usize * height as usize)
+            .flat_map(|pixel| {
+                let [b, g, r, a] = if lsb_first {
+                    [pixel[0], pixel[1], pixel[2], pixel[3]]
+                } else {
+                    [pixel[3], pixel[2], pixel[1], pixel[0]]
+                };
+
+                if image.depth == 24 {
+                    return [r, g, b, 255];
+                }
+                match a {
+                    0 => [0, 0, 0, 0],
+                    255 => [r, g, b, 255],
+                    a => {
+                        let unpremultiply = |c: u8| ((c as u32 * 255) / a as u32).min(255) as u8;
+                        [unpremultiply(r), unpremultiply(g), unpremultiply(b), a]
+                    }
+                }
+            })
+            .collect();
+
+        RgbaImage::from_raw(width as u32, height as u32, rgba_data)
+    }
+
+    fn get_window_title(&self, win: Window) -> Option<String> {
+        let reply = self
+            .conn
+            .get_property(false, win, self.atoms["_NET_WM_NAME"], self.atoms["UTF8_STRING"], 0, u32::MAX)
+            .ok()?
+            .reply()
+            .ok()?;
+        if !reply.value.is_empty() {
+            return Some(String::from_utf8_lossy(&reply.value).into_owned());
+        }
+
+        let reply = self
+            .conn
+            .get_property(false, win, AtomEnum::WM_NAME, AtomEnum::STRING, 0, u32::MAX)
+            .ok()?
+            .reply()
+            .ok()?;
+        Some(String::from_utf8_lossy(&reply.value).into_owned())
+    }
+
+    /// Class part of `WM_CLASS`, falling back to the instance name.
+    fn get_window_class(&self, win: Window) -> Option<String> {
+        let reply = self
+            .conn
+            .get_property(false, win, AtomEnum::WM_CLASS, AtomEnum::STRING, 0, u32::MAX)
+            .ok()?
+            .reply()
+            .ok()?;
+
+        let mut parts = reply.value.split(|&b| b == 0).filter(|part| !part.is_empty());
+        let instance = parts.next();
+        parts
+            .next()
+            .or(instance)
+            .map(|part| String::from_utf8_lossy(part).into_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::process::{Child, Command, Stdio};
+    use std::time::Instant;
+
+    /// A private Xvfb server, killed on drop.
+    struct Xvfb {
+        process: Child,
+        display: String,
+    }
+
+    impl Xvfb {
+        /// Starts a server on a free display, or returns `None` when Xvfb is
+        /// not installed.
+        fn start() -> Option<Self> {
+            let mut process = Command::new("Xvfb")
+                .args(["-displayfd", "1", "-screen", "0", "640x480x24", "-nolisten", "tcp"])
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .spawn()
+                .ok()?;
+
+            let mut display = String::new();
+            BufReader::new(process.stdout.take()?).read_line(&mut display).ok()?;
+            Some(Self {
+                process,
+                display: format!(":{}", display.trim()),
+            })
+        }
+    }
+
+    impl Drop for Xvfb {
+        fn drop(&mut self) {
+            let _ = self.process.kill();
+            let _ = self.process.wait();
+        }
+    }
+
+    fn wait_for<T>(mut check: impl FnMut() -> Option<T>) -> T {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            if let Some(value) = check() {
+                return value;
+            }
+            assert!(Instant::now() < deadline, "timed out waiting for the X server");
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    fn wait_for_event<T>(conn: &RustConnection, mut matches: impl FnMut(Event) -> Option<T>) -> T {
+        wait_for(|| {
+            while let Some(event) = conn.poll_for_event().unwrap() {
+                if let Some(value) = matches(event) {
+                    return Some(value);
+                }
+            }
+            None
+        })
+    }
+
+    fn position(conn: &RustConnection, window: Window) -> (i16, i16) {
+        let geometry = conn.get_geometry(window).unwrap().reply().unwrap();
+        (geometry.x, geometry.y)
+    }
+
+    /// Docks a red icon from a minimal XEmbed client, then checks that its
+    /// container takes no input, that the icon is captured, and that clicks
+    /// reach the client and leave the container hidden again.
+    #[test]
+    fn embeds_and_clicks_a_client_icon() {
+        let Some(xvfb) = Xvfb::start() else {
+            eprintln!("Xvfb is not installed, skipping");
+            return;
+        };
+
+        let (conn, screen_num) = x11rb::connect(Some(&xvfb.display)).unwrap();
+        let tray = Arc::new(XembedTray::acquire(conn, screen_num).unwrap().expect("the selection is free"));
+
+        let (client, screen_num) = x11rb::connect(Some(&xvfb.display)).unwrap();
+        let screen = &client.setup().roots[screen_num];
+        let icon = client.generate_id().unwrap();
+        client
+            .create_window(
+                x11rb::COPY_DEPTH_FROM_PARENT,
+                icon,
+                screen.root,
+                0,
+                0,
+                ICON_SIZE,
+                ICON_SIZE,
+                0,
+                WindowClass::INPUT_OUTPUT,
+                x11rb::COPY_FROM_PARENT,
+                &CreateWindowAux::new().background_pixel(0xff0000).event_mask(
+                    EventMask::STRUCTURE_NOTIFY | EventMask::BUTTON_PRESS | EventMask::BUTTON_RELEASE,
+                ),
+            )
+            .unwrap();
+
+        let intern = |name: &str| client.intern_atom(false, name.as_bytes()).unwrap().reply().unwrap().atom;
+        let selection = intern(&format!("_NET_SYSTEM_TRAY_S{}", screen_num));
+        let opcode = intern("_NET_SYSTEM_TRAY_OPCODE");
+        let xembed = intern("_XEMBED");
+        let owner = client.get_selection_owner(selection).unwrap().reply().unwrap().owner;
+        assert_eq!(owner, tray.owner);
+
+        let dock = ClientMessageEvent::new(32, owner, opcode, [CURRENT_TIME, SYSTEM_TRAY_REQUEST_DOCK, icon, 0, 0]);
+        client.send_event(false, owner, EventMask::NO_EVENT, dock).unwrap();
+        client.flush().unwrap();
+
+        // What run() does on a dock request, without a panel to add items to
+        let requested = wait_for_event(&tray.conn, |event| match event {
+            Event::ClientMessage(event) if event.type_ == tray.atoms["_NET_SYSTEM_TRAY_OPCODE"] => {
+                Some(event.data.as_data32()[2])
+            }
+            _ => None,
+        });
+        assert_eq!(requested, icon);
+        let embedded = tray.embed(icon).unwrap();
+        let container = embedded.container;
+        tray.icons.lock().unwrap().insert(icon, embedded);
+
+        let parent = wait_for_event(&client, |event| match event {
+            Event::ReparentNotify(event) if event.window == icon => Some(event.parent),
+            _ => None,
+        });
+        assert_eq!(parent, container);
+        wait_for_event(&client, |event| match event {
+            Event::ClientMessage(event)
+                if event.type_ == xembed && event.data.as_data32()[1] == XEMBED_EMBEDDED_NOTIFY =>
+            {
+                Some(())
+            }
+            _ => None,
+        });
+
+        assert_eq!(position(&tray.conn, container), (HIDDEN_POSITION, HIDDEN_POSITION));
+        let region = tray.conn.generate_id().unwrap();
+        tray.conn
+            .xfixes_create_region_from_window(region, container, shape::SK::INPUT)
+            .unwrap();
+        let input = tray.conn.xfixes_fetch_region(region).unwrap().reply().unwrap();
+        assert!(input.rectangles.is_empty(), "the container takes input: {:?}", input.rectangles);
+        tray.conn.xfixes_destroy_region(region).unwrap();
+
+        let image = wait_for(|| tray.capture(icon).filter(|image| image.get_pixel(0, 0).0 == [255, 0, 0, 255]));
+        assert_eq!(image.dimensions(), (ICON_SIZE as u32, ICON_SIZE as u32));
+
+        XembedTray::click(&tray, icon, 3, 100, 200).unwrap();
+        let pressed = wait_for_event(&client, |event| match event {
+            Event::ButtonPress(event) => Some(event.detail),
+            _ => None,
+        });
+        assert_eq!(pressed, 3);
+        let released = wait_for_event(&client, |event| match event {
+            Event::ButtonRelease(event) => Some(event.detail),
+            _ => None,
+        });
+        assert_eq!(released, 3);
+        assert_eq!(position(&tray.conn, container), (100, 200));
+
+        wait_for(|| (position(&tray.conn, container) == (HIDDEN_POSITION, HIDDEN_POSITION)).then_some(()));
+    }
+}
@@ -0,0 +1,94 @@
+use serde::Serialize;
+use std::fmt;
+
+/// Window manager failure, sent to the frontend as `{ kind, message }`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", content = "message", rename_all = "kebab-case")]
+pub enum WindowManagerError {
+    /// The display server connection is gone
+    ConnectionLost(String),
+    /// No supported window system or management protocol
+    ProtocolUnavailable(String),
+    WindowNotFound(String),
+    /// A window id that is not valid for the backend
+    InvalidId(String),
+    /// The backend can't perform the action; see `WindowCapabilities`
+    Unsupported(String),
+    Backend(String),
+}
+
+impl fmt::Display for WindowManagerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ConnectionLost(message)
+            | Self::ProtocolUnavailable(message)
+            | Self::WindowNotFound(message)
+            | Self::InvalidId(message)
+            | Self::Unsupported(message)
+            | Self::Backend(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for WindowManagerError {}
+
+impl From<String> for WindowManagerError {
+    fn from(message: String) -> Self {
+        Self::Backend(message)
+    }
+}
+
+impl From<&str> for WindowManagerError {
+    fn from(message: &str) -> Self {
+        Self::Backend(message.to_string())
+    }
+}
+
+impl From<Box<dyn std::error::Error>> for WindowManagerError {
+    fn from(error: Box<dyn std::error::Error>) -> Self {
+        // Helpers bubble X11 errors up boxed; keep their kind
+        #[cfg(feature = "x11")]
+        let error = match error.downcast::<x11rb::errors::ReplyError>() {
+            Ok(error) => return (*error).into(),
+            Err(error) => match error.downcast::<x11rb::errors::ConnectionError>() {
+                Ok(error) => return (*error).into(),
+                Err(error) => error,
+            },
+        };
+
+        Self::Backend(error.to_string())
+    }
+}
+
+impl From<std::num::ParseIntError> for WindowManagerError {
+    fn from(error: std::num::ParseIntError) -> Self {
+        Self::InvalidId(format!("Id de ventana inválido: {}", error))
+    }
+}
+
+#[cfg(feature = "x11")]
+impl From<x11rb::errors::ConnectionError> for WindowManagerError {
+    fn from(error: x11rb::errors::ConnectionError) -> Self {
+        Self::ConnectionLost(format!("Conexión con X11 perdida: {}", error))
+    }
+}
+
+#[cfg(feature = "x11")]
+impl From<x11rb::errors::ConnectError> for WindowManagerError {
+    fn from(error: x11rb::errors::ConnectError) -> Self {
+        Self::ProtocolUnavailable(format!("No se pudo conectar con X11: {}", error))
+    }
+}
+
+#[cfg(feature = "x11")]
+impl From<x11rb::errors::ReplyError> for WindowManagerError {
+    fn from(error: x11rb::errors::ReplyError) -> Self {
+        match error {
+            x11rb::errors::ReplyError::ConnectionError(error) => error.into(),
+            x11rb::errors::ReplyError::X11Error(error) if error.error_kind == x11rb::protocol::ErrorKind::Window => {
+                Self::WindowNotFound(format!("La ventana {} no existe", error.bad_value))
+            }
+            x11rb::errors::ReplyError::X11Error(error) => Self::Backend(format!("Error de X11: {:?}", error)),
+        }
+    }
+}
@@ -1,24 +1,245 @@
+pub mod error;
 #[cfg(feature = "wayland")]
 pub mod wayland;
 #[cfg(feature = "x11")]
 pub mod x11;
 
+use crate::config::SortMode;
+use crate::desktop_entry::DesktopIndex;
 use serde::{Deserialize, Serialize};
 use std::sync::mpsc::Sender;
 
+pub use error::WindowManagerError;
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct WindowInfo {
     pub id: String,
     pub title: String,
     pub is_minimized: bool,
+    /// Whether the window has the focus
+    pub is_active: bool,
+    pub is_maximized: bool,
+    pub is_fullscreen: bool,
+    /// Kept above other windows
+    pub is_above: bool,
+    /// Shown on every desktop
+    pub is_sticky: bool,
+    /// Themed icon name, from the desktop entry when one matches
     pub icon: String,
+    /// Base64 PNG of the icon the window provides itself, when it does
+    pub icon_data: Option<String>,
+    /// WM_CLASS on X11, app_id on Wayland
+    pub app_id: String,
+    /// Desktop file id of the application, when one matches
+    pub desktop_id: Option<String>,
+    /// Localized application name from the desktop entry
+    pub app_name: Option<String>,
     pub demands_attention: Option<bool>,
+    /// Zero-based desktop, `None` when unknown or shown on all of them
+    pub workspace: Option<u32>,
+}
+
+impl WindowInfo {
+    /// Fills in the desktop entry fields from the window's `app_id`.
+    fn resolve_desktop_entry(&mut self, index: &DesktopIndex) {
+        let Some(entry) = index.lookup(&self.app_id) else {
+            return;
+        };
+
+        if let Some(icon) = &entry.icon {
+            self.icon = icon.clone();
+        }
+        self.desktop_id = Some(entry.id.clone());
+        self.app_name = Some(entry.name.clone());
+    }
+}
+
+/// Orders windows by `mode`. Backends list windows in creation order, which
+/// the stable sort keeps for ties.
+pub fn sort_windows(windows: &mut [WindowInfo], mode: SortMode) {
+    match mode {
+        SortMode::Creation => {}
+        SortMode::AppId => windows.sort_by_cached_key(|window| window.app_id.to_lowercase()),
+        SortMode::Title => windows.sort_by_cached_key(|window| window.title.to_lowercase()),
+        SortMode::Workspace => windows.sort_by_key(|window| (window.workspace.is_none(), window.workspace)),
+    }
+}
+
+/// Windows of one application, shown as a single button in grouped mode.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WindowGroup {
+    pub app_id: String,
+    pub desktop_id: Option<String>,
+    pub windows: Vec<WindowInfo>,
+    pub any_active: bool,
+    pub any_urgent: bool,
+}
+
+/// What `get_windows` returns: single windows, or groups in grouped mode.
+#[derive(Debug, Serialize, Clone)]
+#[serde(untagged)]
+pub enum WindowList {
+    Windows(Vec<WindowInfo>),
+    Groups(Vec<WindowGroup>),
+}
+
+/// Groups windows by desktop entry, or by app_id when none matched. Groups
+/// appear where their first window was, so the sort mode still applies.
+pub fn group_windows(windows: Vec<WindowInfo>) -> Vec<WindowGroup> {
+    let mut groups: Vec<WindowGroup> = Vec::new();
+
+    for window in windows {
+        let key = window.desktop_id.clone().unwrap_or_else(|| window.app_id.to_lowercase());
+        let position = groups
+            .iter()
+            .position(|group| group.desktop_id.clone().unwrap_or_else(|| group.app_id.to_lowercase()) == key);
+
+        let group = match position {
+            Some(position) => &mut groups[position],
+            None => {
+                groups.push(WindowGroup {
+                    app_id: window.app_id.clone(),
+                    desktop_id: window.desktop_id.clone(),
+                    windows: Vec::new(),
+                    any_active: false,
+                    any_urgent: false,
+                });
+                groups.last_mut().expect("group just pushed")
+            }
+        };
+
+        group.any_active |= window.is_active;
+        group.any_urgent |= window.demands_attention.unwrap_or(false);
+        group.windows.push(window);
+    }
+
+    groups
+}
+
+/// What clicking a group does, decided by `GroupToggle::decide`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GroupToggle {
+    /// Every window is minimized: restore them all, focusing the first
+    RestoreAll(Vec<String>),
+    /// Focus this window, restoring it if needed
+    Activate(String),
+    /// The only window has the focus: minimize it
+    Minimize(String),
+}
+
+impl GroupToggle {
+    /// Cycles focus to the window after the active one, restores everything
+    /// when all are minimized, and otherwise focuses the first visible one.
+    /// `windows` holds the id, minimized and active state of each window, in
+    /// the order they are shown.
+    pub fn decide(windows: &[(String, bool, bool)]) -> Option<Self> {
+        if windows.is_empty() {
+            return None;
+        }
+
+        if windows.iter().all(|(_, is_minimized, _)| *is_minimized) {
+            return Some(Self::RestoreAll(windows.iter().map(|(id, _, _)| id.clone()).collect()));
+        }
+
+        let toggle = match windows.iter().position(|(_, _, is_active)| *is_active) {
+            Some(active) if windows.len() == 1 => Self::Minimize(windows[active].0.clone()),
+            Some(active) => Self::Activate(windows[(active + 1) % windows.len()].0.clone()),
+            None => {
+                let (id, _, _) = windows
+                    .iter()
+                    .find(|(_, is_minimized, _)| !is_minimized)
+                    .unwrap_or(&windows[0]);
+                Self::Activate(id.clone())
+            }
+        };
+
+        Some(toggle)
+    }
+}
+
+/// Window actions a backend supports, so the UI can grey out the rest.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct WindowCapabilities {
+    pub close: bool,
+    pub maximize: bool,
+    pub fullscreen: bool,
+    pub minimize: bool,
+    pub always_on_top: bool,
+    pub sticky: bool,
+    pub move_to_desktop: bool,
+    /// Number of desktops, when the backend knows it
+    pub desktops: Option<u32>,
+}
+
+/// An action from the window context menu, e.g. `{ action: "set-maximized", value: true }`.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(tag = "action", content = "value", rename_all = "kebab-case")]
+pub enum WindowAction {
+    Close,
+    SetMaximized(bool),
+    SetFullscreen(bool),
+    SetMinimized(bool),
+    SetAlwaysOnTop(bool),
+    SetSticky(bool),
+    MoveToDesktop(u32),
+}
+
+fn unsupported(action: &str) -> WindowManagerError {
+    WindowManagerError::Unsupported(format!("{} no está soportado por este backend", action))
 }
 
 pub trait WindowManagerBackend {
-    fn get_window_list(&mut self) -> Result<Vec<WindowInfo>, Box<dyn std::error::Error>>;
-    fn setup_event_monitoring(&mut self, tx: Sender<()>) -> Result<(), Box<dyn std::error::Error>>;
-    fn toggle_window(&self, win_id: &str) -> Result<(), Box<dyn std::error::Error>>;
+    fn get_window_list(&mut self) -> Result<Vec<WindowInfo>, WindowManagerError>;
+    fn setup_event_monitoring(&mut self, tx: Sender<()>) -> Result<(), WindowManagerError>;
+    fn toggle_window(&self, win_id: &str) -> Result<(), WindowManagerError>;
+    /// Cycles focus through a group's windows, or restores all of them when
+    /// they are all minimized; see `GroupToggle::decide`.
+    fn toggle_group(&self, win_ids: &[String]) -> Result<(), WindowManagerError>;
+
+    /// Focused window, for backends that can tell.
+    fn get_active_window(&self) -> Result<Option<String>, WindowManagerError> {
+        Ok(None)
+    }
+
+    /// Pixel size to render the icons windows provide at, for backends that
+    /// read them.
+    fn set_icon_size(&mut self, _size: u32) {}
+
+    /// Actions below that the backend implements. The defaults of those
+    /// methods fail with `Unsupported`.
+    fn capabilities(&self) -> WindowCapabilities {
+        WindowCapabilities::default()
+    }
+
+    fn close_window(&self, _win_id: &str) -> Result<(), WindowManagerError> {
+        Err(unsupported("Cerrar ventanas"))
+    }
+
+    fn set_maximized(&self, _win_id: &str, _maximized: bool) -> Result<(), WindowManagerError> {
+        Err(unsupported("Maximizar ventanas"))
+    }
+
+    fn set_fullscreen(&self, _win_id: &str, _fullscreen: bool) -> Result<(), WindowManagerError> {
+        Err(unsupported("Pantalla completa"))
+    }
+
+    fn set_minimized(&self, _win_id: &str, _minimized: bool) -> Result<(), WindowManagerError> {
+        Err(unsupported("Minimizar ventanas"))
+    }
+
+    fn set_always_on_top(&self, _win_id: &str, _above: bool) -> Result<(), WindowManagerError> {
+        Err(unsupported("Mantener encima"))
+    }
+
+    /// Shows the window on every desktop.
+    fn set_sticky(&self, _win_id: &str, _sticky: bool) -> Result<(), WindowManagerError> {
+        Err(unsupported("Mostrar en todos los escritorios"))
+    }
+
+    /// Moves the window to the zero-based `desktop`.
+    fn move_to_desktop(&self, _win_id: &str, _desktop: u32) -> Result<(), WindowManagerError> {
+        Err(unsupported("Mover a otro escritorio"))
+    }
 }
 
 pub struct WindowManager {
@@ -26,7 +247,7 @@ pub struct WindowManager {
 }
 
 impl WindowManager {
-    pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
+    pub fn new() -> Result<Self, WindowManagerError> {
         #[cfg(feature = "wayland")]
         if std::env::var("WAYLAND_DISPLAY").is_ok() {
             match wayland::WaylandManager::new() {
@@ -58,14 +279,103 @@ impl WindowManager {
             });
         }
 
-        Err("No supported window system found".into())
+        Err(WindowManagerError::ProtocolUnavailable("No supported window system found".to_string()))
+    }
+
+    /// Lists the windows, matched to applications in `index`.
+    pub fn get_window_list(&mut self, index: &DesktopIndex) -> Result<Vec<WindowInfo>, WindowManagerError> {
+        let mut windows = self.backend.get_window_list()?;
+
+        for window in &mut windows {
+            window.resolve_desktop_entry(index);
+        }
+
+        Ok(windows)
     }
 
-    pub fn get_window_list(&mut self) -> Result<Vec<WindowInfo>, Box<dyn std::error::Error>> {
-        self.backend.get_window_list()
+    pub fn set_icon_size(&mut self, size: u32) {
+        self.backend.set_icon_size(size)
     }
 
-    pub fn toggle_window(&self, win_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+    pub fn toggle_window(&self, win_id: &str) -> Result<(), WindowManagerError> {
         self.backend.toggle_window(win_id)
     }
+
+    pub fn toggle_group(&self, win_ids: &[String]) -> Result<(), WindowManagerError> {
+        self.backend.toggle_group(win_ids)
+    }
+
+    pub fn get_active_window(&self) -> Result<Option<String>, WindowManagerError> {
+        self.backend.get_active_window()
+    }
+
+    pub fn capabilities(&self) -> WindowCapabilities {
+        self.backend.capabilities()
+    }
+
+    pub fn perform(&self, win_id: &str, action: WindowAction) -> Result<(), WindowManagerError> {
+        match action {
+            WindowAction::Close => self.backend.close_window(win_id),
+            WindowAction::SetMaximized(maximized) => self.backend.set_maximized(win_id, maximized),
+            WindowAction::SetFullscreen(fullscreen) => self.backend.set_fullscreen(win_id, fullscreen),
+            WindowAction::SetMinimized(minimized) => self.backend.set_minimized(win_id, minimized),
+            WindowAction::SetAlwaysOnTop(above) => self.backend.set_always_on_top(win_id, above),
+            WindowAction::SetSticky(sticky) => self.backend.set_sticky(win_id, sticky),
+            WindowAction::MoveToDesktop(desktop) => self.backend.move_to_desktop(win_id, desktop),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `(id, is_minimized, is_active)` for each window, as `decide` takes them.
+    fn windows(states: &[(&str, bool, bool)]) -> Vec<(String, bool, bool)> {
+        states
+            .iter()
+            .map(|(id, is_minimized, is_active)| (id.to_string(), *is_minimized, *is_active))
+            .collect()
+    }
+
+    #[test]
+    fn restores_every_window_when_all_are_minimized() {
+        let group = windows(&[("1", true, false), ("2", true, false)]);
+        assert_eq!(
+            GroupToggle::decide(&group),
+            Some(GroupToggle::RestoreAll(vec!["1".to_string(), "2".to_string()]))
+        );
+    }
+
+    #[test]
+    fn cycles_to_the_window_after_the_active_one() {
+        let group = windows(&[("1", false, false), ("2", false, true), ("3", true, false)]);
+        assert_eq!(GroupToggle::decide(&group), Some(GroupToggle::Activate("3".to_string())));
+
+        let group = windows(&[("1", false, false), ("2", false, false), ("3", false, true)]);
+        assert_eq!(GroupToggle::decide(&group), Some(GroupToggle::Activate("1".to_string())));
+    }
+
+    #[test]
+    fn focuses_the_first_visible_window_when_none_is_active() {
+        let group = windows(&[("1", true, false), ("2", false, false), ("3", false, false)]);
+        assert_eq!(GroupToggle::decide(&group), Some(GroupToggle::Activate("2".to_string())));
+    }
+
+    #[test]
+    fn toggles_a_single_window() {
+        assert_eq!(
+            GroupToggle::decide(&windows(&[("1", false, true)])),
+            Some(GroupToggle::Minimize("1".to_string()))
+        );
+        assert_eq!(
+            GroupToggle::decide(&windows(&[("1", false, false)])),
+            Some(GroupToggle::Activate("1".to_string()))
+        );
+        assert_eq!(
+            GroupToggle::decide(&windows(&[("1", true, false)])),
+            Some(GroupToggle::RestoreAll(vec!["1".to_string()]))
+        );
+        assert_eq!(GroupToggle::decide(&[]), None);
+    }
 }
@@ -1,4 +1,4 @@
-use super::{WindowInfo, WindowManagerBackend};
+use super::{GroupToggle, WindowCapabilities, WindowInfo, WindowManagerBackend, WindowManagerError};
 use std::collections::HashMap;
 use std::sync::mpsc::Sender;
 use std::sync::{Arc, Mutex};
@@ -12,6 +12,9 @@ use wayland_protocols_wlr::foreign_toplevel::v1::client::{
 };
 
 // Import KDE Plasma protocols
+use wayland_protocols_plasma::plasma_virtual_desktop::client::org_kde_plasma_virtual_desktop_management::{
+    self, OrgKdePlasmaVirtualDesktopManagement,
+};
 use wayland_protocols_plasma::plasma_window_management::client::{
     org_kde_plasma_window::{self, OrgKdePlasmaWindow},
     org_kde_plasma_window_management::{self, OrgKdePlasmaWindowManagement},
@@ -23,6 +26,22 @@ enum WindowType {
     Kde(KdeToplevelInfo),
 }
 
+impl WindowType {
+    fn is_minimized(&self) -> bool {
+        match self {
+            Self::Wlr(toplevel) => toplevel.is_minimized,
+            Self::Kde(toplevel) => toplevel.is_minimized,
+        }
+    }
+
+    fn is_activated(&self) -> bool {
+        match self {
+            Self::Wlr(toplevel) => toplevel.is_activated,
+            Self::Kde(toplevel) => toplevel.is_activated,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 struct ToplevelInfo {
     handle: ZwlrForeignToplevelHandleV1,
@@ -32,6 +51,8 @@ struct ToplevelInfo {
     is_minimized: bool,
     is_activated: bool,
     is_fullscreen: bool,
+    /// Order in which the compositor announced the toplevel
+    sequence: u64,
 }
 
 #[derive(Debug, Clone)]
@@ -43,11 +64,16 @@ struct KdeToplevelInfo {
     is_minimized: bool,
     is_activated: bool,
     is_fullscreen: bool,
+    keep_above: bool,
+    on_all_desktops: bool,
+    /// Windows such as panels and popups ask to stay out of the taskbar
+    skip_taskbar: bool,
     desktop: i32,
+    sequence: u64,
 }
 
 impl ToplevelInfo {
-    fn new_wlr(handle: ZwlrForeignToplevelHandleV1) -> Self {
+    fn new_wlr(handle: ZwlrForeignToplevelHandleV1, sequence: u64) -> Self {
         Self {
             handle,
             title: String::new(),
@@ -56,6 +82,7 @@ impl ToplevelInfo {
             is_minimized: false,
             is_activated: false,
             is_fullscreen: false,
+            sequence,
         }
     }
 
@@ -64,8 +91,18 @@ impl ToplevelInfo {
             id: id.to_string(),
             title: self.title.clone(),
             is_minimized: self.is_minimized,
+            is_active: self.is_activated,
+            is_maximized: self.is_maximized,
+            is_fullscreen: self.is_fullscreen,
+            is_above: false, // Not part of wlr-foreign-toplevel
+            is_sticky: false,
             icon: self.app_id.clone(),
+            icon_data: None, // Toplevel protocols only give the app_id
+            app_id: self.app_id.clone(),
+            desktop_id: None,
+            app_name: None,
             demands_attention: None, // Wayland doesn't have direct equivalent
+            workspace: None, // wlr-foreign-toplevel doesn't expose workspaces
         }
     }
 
@@ -93,7 +130,7 @@ impl ToplevelInfo {
 }
 
 impl KdeToplevelInfo {
-    fn new_kde(handle: OrgKdePlasmaWindow) -> Self {
+    fn new_kde(handle: OrgKdePlasmaWindow, sequence: u64) -> Self {
         Self {
             handle,
             title: String::new(),
@@ -102,7 +139,11 @@ impl KdeToplevelInfo {
             is_minimized: false,
             is_activated: false,
             is_fullscreen: false,
+            keep_above: false,
+            on_all_desktops: false,
+            skip_taskbar: false,
             desktop: 0,
+            sequence,
         }
     }
 
@@ -111,8 +152,18 @@ impl KdeToplevelInfo {
             id: id.to_string(),
             title: self.title.clone(),
             is_minimized: self.is_minimized,
+            is_active: self.is_activated,
+            is_maximized: self.is_maximized,
+            is_fullscreen: self.is_fullscreen,
+            is_above: self.keep_above,
+            is_sticky: self.on_all_desktops,
             icon: self.app_id.clone(),
+            icon_data: None, // Plasma sends icons through a pipe; the themed name is enough
+            app_id: self.app_id.clone(),
+            desktop_id: None,
+            app_name: None,
             demands_attention: None, // KDE doesn't have direct equivalent
+            workspace: u32::try_from(self.desktop).ok().filter(|_| !self.on_all_desktops),
         }
     }
 
@@ -135,7 +186,7 @@ impl KdeToplevelInfo {
         ];
         
         let app_id_lower = self.app_id.to_lowercase();
-        !skip_apps.iter().any(|app| app_id_lower.contains(app))
+        !self.skip_taskbar && !skip_apps.iter().any(|app| app_id_lower.contains(app))
     }
 }
 
@@ -151,9 +202,13 @@ struct AppState {
     kde_toplevels: HashMap<u32, KdeToplevelInfo>,
     wlr_manager: Option<ZwlrForeignToplevelManagerV1>,
     kde_manager: Option<OrgKdePlasmaWindowManagement>,
+    kde_desktop_manager: Option<OrgKdePlasmaVirtualDesktopManagement>,
+    /// Ids of the Plasma virtual desktops, in order
+    kde_desktops: Vec<String>,
     seat: Option<wl_seat::WlSeat>,
     event_sender: Option<Sender<()>>,
     protocol_type: Option<ProtocolType>,
+    next_sequence: u64,
 }
 
 impl AppState {
@@ -163,11 +218,20 @@ impl AppState {
             kde_toplevels: HashMap::new(),
             wlr_manager: None,
             kde_manager: None,
+            kde_desktop_manager: None,
+            kde_desktops: Vec::new(),
             seat: None,
             event_sender: None,
             protocol_type: None,
+            next_sequence: 0,
         }
     }
+
+    fn next_sequence(&mut self) -> u64 {
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+        sequence
+    }
 }
 
 pub struct WaylandManager {
@@ -177,9 +241,9 @@ pub struct WaylandManager {
 }
 
 impl WaylandManager {
-    pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
+    pub fn new() -> Result<Self, WindowManagerError> {
         let conn = Connection::connect_to_env()
-            .map_err(|e| format!("Failed to connect to Wayland: {}", e))?;
+            .map_err(|e| WindowManagerError::ProtocolUnavailable(format!("Failed to connect to Wayland: {}", e)))?;
         
         let event_queue = conn.new_event_queue::<AppState>();
         let qh = event_queue.handle();
@@ -195,11 +259,11 @@ impl WaylandManager {
         })
     }
 
-    pub fn setup_protocol_bindings(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    pub fn setup_protocol_bindings(&mut self) -> Result<(), WindowManagerError> {
         // Dispatch pending events to set up protocol bindings
         log::info!("Dispatching events to discover available protocols...");
         self.event_queue.blocking_dispatch(&mut *self.state.lock().unwrap())
-            .map_err(|e| format!("Failed to dispatch events: {}", e))?;
+            .map_err(|e| WindowManagerError::ConnectionLost(format!("Failed to dispatch events: {}", e)))?;
         
         // Check which protocol is available
         let state_guard = self.state.lock().unwrap();
@@ -218,39 +282,121 @@ impl WaylandManager {
         }
         
         drop(state_guard);
-        return Err("No supported window management protocol available (tried wlr-foreign-toplevel-management and KDE Plasma protocols).".into());
+        return Err(WindowManagerError::ProtocolUnavailable("No supported window management protocol available (tried wlr-foreign-toplevel-management and KDE Plasma protocols).".to_string()));
+    }
+}
+
+impl WaylandManager {
+    /// Looks a window up in whichever protocol announced it.
+    fn find_window(&self, win_id: &str) -> Result<WindowType, WindowManagerError> {
+        Self::lookup_window(&self.state.lock().unwrap(), win_id)
+    }
+
+    fn lookup_window(state: &AppState, win_id: &str) -> Result<WindowType, WindowManagerError> {
+        let id: u32 = win_id.parse()
+            .map_err(|_| WindowManagerError::InvalidId(format!("Invalid window ID format: {}", win_id)))?;
+
+        if let Some(toplevel) = state.wlr_toplevels.get(&id) {
+            return Ok(WindowType::Wlr(toplevel.clone()));
+        }
+        if let Some(toplevel) = state.kde_toplevels.get(&id) {
+            return Ok(WindowType::Kde(toplevel.clone()));
+        }
+
+        Err(WindowManagerError::WindowNotFound(format!("Window not found: {}", win_id)))
+    }
+
+    /// Sets or clears one `org_kde_plasma_window_management.state` flag.
+    fn set_kde_state(
+        &self,
+        win_id: &str,
+        flag: org_kde_plasma_window_management::State,
+        enabled: bool,
+        action: &str,
+    ) -> Result<(), WindowManagerError> {
+        match self.find_window(win_id)? {
+            WindowType::Kde(toplevel) => {
+                let flag = flag as u32;
+                toplevel.handle.set_state(flag, if enabled { flag } else { 0 });
+                self.flush()
+            }
+            WindowType::Wlr(_) => Err(WindowManagerError::Unsupported(format!(
+                "{} no está soportado por wlr-foreign-toplevel-management",
+                action
+            ))),
+        }
+    }
+
+    /// Brings a window to the front, restoring it if minimized. wlr needs a
+    /// seat to activate with; Plasma does it through the state flags.
+    fn activate(state: &AppState, window: &WindowType) -> Result<(), WindowManagerError> {
+        match window {
+            WindowType::Wlr(toplevel) => {
+                let seat = state.seat.as_ref().ok_or_else(|| {
+                    WindowManagerError::ProtocolUnavailable("No seat available to activate windows".to_string())
+                })?;
+                if toplevel.is_minimized {
+                    toplevel.handle.unset_minimized();
+                }
+                toplevel.handle.activate(seat);
+            }
+            WindowType::Kde(toplevel) => {
+                let active = org_kde_plasma_window_management::State::Active as u32;
+                let minimized = org_kde_plasma_window_management::State::Minimized as u32;
+                toplevel.handle.set_state(active | minimized, active);
+            }
+        }
+        Ok(())
+    }
+
+    fn minimize(window: &WindowType) {
+        match window {
+            WindowType::Wlr(toplevel) => toplevel.handle.set_minimized(),
+            WindowType::Kde(toplevel) => {
+                let minimized = org_kde_plasma_window_management::State::Minimized as u32;
+                toplevel.handle.set_state(minimized, minimized);
+            }
+        }
+    }
+
+    fn flush(&self) -> Result<(), WindowManagerError> {
+        self.conn
+            .flush()
+            .map_err(|e| WindowManagerError::ConnectionLost(format!("Failed to flush requests: {}", e)))
     }
 }
 
 impl WindowManagerBackend for WaylandManager {
-    fn get_window_list(&mut self) -> Result<Vec<WindowInfo>, Box<dyn std::error::Error>> {
+    fn get_window_list(&mut self) -> Result<Vec<WindowInfo>, WindowManagerError> {
         // Dispatch any pending events first
         if let Err(e) = self.event_queue.blocking_dispatch(&mut *self.state.lock().unwrap()) {
             log::warn!("Failed to dispatch events: {}", e);
-            return Ok(Vec::new());
+            return Err(WindowManagerError::ConnectionLost(format!("Failed to dispatch events: {}", e)));
         }
 
         let state = self.state.lock().unwrap();
-        let mut windows: Vec<WindowInfo> = Vec::new();
+        let mut windows: Vec<(u64, WindowInfo)> = Vec::new();
         
         // Add wlr windows
         for (id, toplevel) in &state.wlr_toplevels {
             if toplevel.should_show() {
-                windows.push(toplevel.to_window_info(&id.to_string()));
+                windows.push((toplevel.sequence, toplevel.to_window_info(&id.to_string())));
             }
         }
         
         // Add KDE windows  
         for (id, toplevel) in &state.kde_toplevels {
             if toplevel.should_show() {
-                windows.push(toplevel.to_window_info(&id.to_string()));
+                windows.push((toplevel.sequence, toplevel.to_window_info(&id.to_string())));
             }
         }
 
-        Ok(windows)
+        // HashMap order changes between calls; keep windows in creation order
+        windows.sort_by_key(|(sequence, _)| *sequence);
+        Ok(windows.into_iter().map(|(_, window)| window).collect())
     }
 
-    fn setup_event_monitoring(&mut self, tx: Sender<()>) -> Result<(), Box<dyn std::error::Error>> {
+    fn setup_event_monitoring(&mut self, tx: Sender<()>) -> Result<(), WindowManagerError> {
         match self.setup_protocol_bindings() {
             Ok(_) => {
                 let protocol_name = {
@@ -300,35 +446,160 @@ impl WindowManagerBackend for WaylandManager {
         Ok(())
     }
 
-    fn toggle_window(&self, win_id: &str) -> Result<(), Box<dyn std::error::Error>> {
-        let id: u32 = win_id.parse()
-            .map_err(|_| "Invalid window ID format")?;
+    fn toggle_window(&self, win_id: &str) -> Result<(), WindowManagerError> {
+        {
+            let state = self.state.lock().unwrap();
+            let window = Self::lookup_window(&state, win_id)?;
 
+            if window.is_activated() && !window.is_minimized() {
+                Self::minimize(&window);
+            } else {
+                Self::activate(&state, &window)?;
+            }
+        }
+        self.flush()
+    }
+
+    fn capabilities(&self) -> WindowCapabilities {
         let state = self.state.lock().unwrap();
-        
-        // Try wlr first
-        if let Some(toplevel) = state.wlr_toplevels.get(&id) {
-            if let Some(seat) = &state.seat {
-                if toplevel.is_minimized {
-                    toplevel.handle.unset_minimized();
-                    toplevel.handle.activate(seat);
-                } else if toplevel.is_activated {
-                    toplevel.handle.set_minimized();
-                } else {
+        let wlr = state.wlr_manager.as_ref();
+        let kde = state.kde_manager.is_some();
+
+        WindowCapabilities {
+            close: wlr.is_some() || kde,
+            maximize: wlr.is_some() || kde,
+            fullscreen: wlr.is_some_and(|manager| manager.version() >= 2) || kde,
+            minimize: wlr.is_some() || kde,
+            always_on_top: kde,
+            sticky: kde,
+            move_to_desktop: kde,
+            desktops: u32::try_from(state.kde_desktops.len()).ok().filter(|desktops| *desktops > 0),
+        }
+    }
+
+    fn close_window(&self, win_id: &str) -> Result<(), WindowManagerError> {
+        match self.find_window(win_id)? {
+            WindowType::Wlr(toplevel) => toplevel.handle.close(),
+            WindowType::Kde(toplevel) => toplevel.handle.close(),
+        }
+        self.flush()
+    }
+
+    fn set_maximized(&self, win_id: &str, maximized: bool) -> Result<(), WindowManagerError> {
+        match self.find_window(win_id)? {
+            WindowType::Wlr(toplevel) if maximized => toplevel.handle.set_maximized(),
+            WindowType::Wlr(toplevel) => toplevel.handle.unset_maximized(),
+            WindowType::Kde(_) => {
+                return self.set_kde_state(
+                    win_id,
+                    org_kde_plasma_window_management::State::Maximized,
+                    maximized,
+                    "Maximizar",
+                )
+            }
+        }
+        self.flush()
+    }
+
+    fn set_fullscreen(&self, win_id: &str, fullscreen: bool) -> Result<(), WindowManagerError> {
+        match self.find_window(win_id)? {
+            WindowType::Wlr(toplevel) if toplevel.handle.version() < 2 => {
+                return Err(WindowManagerError::Unsupported(
+                    "El compositor no permite pantalla completa desde el panel".to_string(),
+                ))
+            }
+            WindowType::Wlr(toplevel) if fullscreen => toplevel.handle.set_fullscreen(None),
+            WindowType::Wlr(toplevel) => toplevel.handle.unset_fullscreen(),
+            WindowType::Kde(_) => {
+                return self.set_kde_state(
+                    win_id,
+                    org_kde_plasma_window_management::State::Fullscreen,
+                    fullscreen,
+                    "Pantalla completa",
+                )
+            }
+        }
+        self.flush()
+    }
+
+    fn set_minimized(&self, win_id: &str, minimized: bool) -> Result<(), WindowManagerError> {
+        match self.find_window(win_id)? {
+            WindowType::Wlr(toplevel) if minimized => toplevel.handle.set_minimized(),
+            WindowType::Wlr(toplevel) => {
+                toplevel.handle.unset_minimized();
+                if let Some(seat) = &self.state.lock().unwrap().seat {
                     toplevel.handle.activate(seat);
                 }
             }
-            return Ok(());
+            WindowType::Kde(_) => {
+                return self.set_kde_state(
+                    win_id,
+                    org_kde_plasma_window_management::State::Minimized,
+                    minimized,
+                    "Minimizar",
+                )
+            }
         }
-        
-        // Try KDE
-        if let Some(_toplevel) = state.kde_toplevels.get(&id) {
-            // TODO: Implement KDE specific window operations
-            log::info!("KDE window toggle not yet implemented for window {}", id);
-            return Ok(());
+        self.flush()
+    }
+
+    fn set_always_on_top(&self, win_id: &str, above: bool) -> Result<(), WindowManagerError> {
+        self.set_kde_state(win_id, org_kde_plasma_window_management::State::KeepAbove, above, "Mantener encima")
+    }
+
+    fn set_sticky(&self, win_id: &str, sticky: bool) -> Result<(), WindowManagerError> {
+        self.set_kde_state(
+            win_id,
+            org_kde_plasma_window_management::State::OnAllDesktops,
+            sticky,
+            "Mostrar en todos los escritorios",
+        )
+    }
+
+    fn move_to_desktop(&self, win_id: &str, desktop: u32) -> Result<(), WindowManagerError> {
+        match self.find_window(win_id)? {
+            WindowType::Kde(toplevel) => {
+                let desktops = self.state.lock().unwrap().kde_desktops.len();
+                if desktop as usize >= desktops {
+                    return Err(WindowManagerError::Backend(format!(
+                        "El escritorio {} no existe (hay {})",
+                        desktop, desktops
+                    )));
+                }
+                toplevel.handle.set_virtual_desktop(desktop);
+                self.flush()
+            }
+            WindowType::Wlr(_) => Err(WindowManagerError::Unsupported(
+                "Mover a otro escritorio no está soportado por wlr-foreign-toplevel-management".to_string(),
+            )),
         }
-        
-        Err("Window not found".into())
+    }
+
+    fn toggle_group(&self, win_ids: &[String]) -> Result<(), WindowManagerError> {
+        {
+            let state = self.state.lock().unwrap();
+
+            let mut windows = HashMap::new();
+            let mut toggle_state = Vec::new();
+            for win_id in win_ids {
+                let window = Self::lookup_window(&state, win_id)?;
+                toggle_state.push((win_id.clone(), window.is_minimized(), window.is_activated()));
+                windows.insert(win_id.as_str(), window);
+            }
+
+            match GroupToggle::decide(&toggle_state) {
+                Some(GroupToggle::RestoreAll(ids)) => {
+                    // The first window is activated last so it ends up on top
+                    for id in ids.iter().rev() {
+                        Self::activate(&state, &windows[id.as_str()])?;
+                    }
+                }
+                Some(GroupToggle::Activate(id)) => Self::activate(&state, &windows[id.as_str()])?,
+                Some(GroupToggle::Minimize(id)) => Self::minimize(&windows[id.as_str()]),
+                None => {}
+            }
+        }
+        self.flush()
     }
 }
 
@@ -347,9 +618,10 @@ impl Dispatch<wl_registry::WlRegistry, ()> for AppState {
             match interface.as_str() {
                 "zwlr_foreign_toplevel_manager_v1" => {
                     if version >= 1 {
+                        // Version 2 adds set_fullscreen to the handles
                         let manager = registry.bind::<ZwlrForeignToplevelManagerV1, _, _>(
                             name, 
-                            1.min(version), 
+                            2.min(version), 
                             qh, 
                             ()
                         );
@@ -371,6 +643,16 @@ impl Dispatch<wl_registry::WlRegistry, ()> for AppState {
                         log::info!("Found and bound KDE Plasma window management protocol");
                     }
                 }
+                "org_kde_plasma_virtual_desktop_management" => {
+                    // Only used to know how many desktops there are
+                    let manager = registry.bind::<OrgKdePlasmaVirtualDesktopManagement, _, _>(
+                        name,
+                        1.min(version),
+                        qh,
+                        ()
+                    );
+                    state.kde_desktop_manager = Some(manager);
+                }
                 "wl_seat" => {
                     if version >= 1 {
                         let seat = registry.bind::<wl_seat::WlSeat, _, _>(
@@ -401,7 +683,8 @@ impl Dispatch<ZwlrForeignToplevelManagerV1, ()> for AppState {
         match event {
             zwlr_foreign_toplevel_manager_v1::Event::Toplevel { toplevel } => {
                 let id = toplevel.id().protocol_id();
-                let info = ToplevelInfo::new_wlr(toplevel);
+                let sequence = state.next_sequence();
+                let info = ToplevelInfo::new_wlr(toplevel, sequence);
                 state.wlr_toplevels.insert(id, info);
             }
             zwlr_foreign_toplevel_manager_v1::Event::Finished => {
@@ -504,17 +787,40 @@ impl Dispatch<wl_output::WlOutput, ()> for AppState {
 // Implement Dispatch for KDE Plasma window management
 impl Dispatch<OrgKdePlasmaWindowManagement, ()> for AppState {
     fn event(
-        _state: &mut Self,
-        _: &OrgKdePlasmaWindowManagement,
+        state: &mut Self,
+        manager: &OrgKdePlasmaWindowManagement,
         event: org_kde_plasma_window_management::Event,
         _: &(),
         _: &Connection,
+        qh: &QueueHandle<AppState>,
+    ) {
+        if let org_kde_plasma_window_management::Event::Window { id } = event {
+            // Windows are keyed by their handle, like wlr toplevels
+            let handle = manager.get_window(id, qh, ());
+            let window_id = handle.id().protocol_id();
+            let sequence = state.next_sequence();
+            state.kde_toplevels.insert(window_id, KdeToplevelInfo::new_kde(handle, sequence));
+        }
+    }
+}
+
+// Implement Dispatch for KDE Plasma virtual desktops
+impl Dispatch<OrgKdePlasmaVirtualDesktopManagement, ()> for AppState {
+    fn event(
+        state: &mut Self,
+        _: &OrgKdePlasmaVirtualDesktopManagement,
+        event: org_kde_plasma_virtual_desktop_management::Event,
+        _: &(),
+        _: &Connection,
         _: &QueueHandle<AppState>,
     ) {
         match event {
-            org_kde_plasma_window_management::Event::Window { id } => {
-                // TODO: Properly handle KDE window creation
-                log::info!("KDE window management event for window id: {}", id);
+            org_kde_plasma_virtual_desktop_management::Event::DesktopCreated { desktop_id, position } => {
+                let position = (position as usize).min(state.kde_desktops.len());
+                state.kde_desktops.insert(position, desktop_id);
+            }
+            org_kde_plasma_virtual_desktop_management::Event::DesktopRemoved { desktop_id } => {
+                state.kde_desktops.retain(|id| *id != desktop_id);
             }
             _ => {}
         }
@@ -542,11 +848,16 @@ impl Dispatch<OrgKdePlasmaWindow, ()> for AppState {
                     window_info.app_id = app_id;
                 }
                 org_kde_plasma_window::Event::StateChanged { flags } => {
-                    // TODO: Parse KDE state flags properly
-                    window_info.is_minimized = flags & 1 != 0; // Assuming bit 0 is minimized
-                    window_info.is_activated = flags & 2 != 0; // Assuming bit 1 is activated  
-                    window_info.is_maximized = flags & 4 != 0; // Assuming bit 2 is maximized
-                    window_info.is_fullscreen = flags & 8 != 0; // Assuming bit 3 is fullscreen
+                    use org_kde_plasma_window_management::State;
+                    let has = |flag: State| flags & flag as u32 != 0;
+
+                    window_info.is_activated = has(State::Active);
+                    window_info.is_minimized = has(State::Minimized);
+                    window_info.is_maximized = has(State::Maximized);
+                    window_info.is_fullscreen = has(State::Fullscreen);
+                    window_info.keep_above = has(State::KeepAbove);
+                    window_info.on_all_desktops = has(State::OnAllDesktops);
+                    window_info.skip_taskbar = has(State::Skiptaskbar);
                 }
                 org_kde_plasma_window::Event::VirtualDesktopChanged { number } => {
                     window_info.desktop = number;
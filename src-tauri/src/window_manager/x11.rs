@@ -1,23 +1,55 @@
-use super::{WindowInfo, WindowManagerBackend};
-use std::collections::HashMap;
+use super::{GroupToggle, WindowCapabilities, WindowInfo, WindowManagerBackend, WindowManagerError};
+use crate::tray::pixmap::{self, SniPixmap};
+use image::{Rgba, RgbaImage};
+use std::collections::{HashMap, HashSet};
 // Ordering ya no es necesario si quitamos AtomicBool
 // use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::Sender;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::thread;
 use x11rb::connection::Connection;
 use x11rb::protocol::xproto::*;
 use x11rb::protocol::Event;
 use x11rb::CURRENT_TIME;
 
+/// Icon size until the panel reports its height: 24px buttons at 2x.
+const DEFAULT_ICON_SIZE: u32 = 48;
+/// `WM_HINTS` flags for the legacy icon pixmap and its mask.
+const ICON_PIXMAP_HINT: u32 = 1 << 2;
+const ICON_MASK_HINT: u32 = 1 << 5;
+/// Client window properties that change what the panel shows, besides
+/// `WM_HINTS`, which is predefined.
+const WATCHED_PROPERTIES: [&str; 5] = [
+    "_NET_WM_NAME",
+    "WM_NAME",
+    "_NET_WM_STATE",
+    "_NET_WM_DESKTOP",
+    "_NET_WM_ICON",
+];
+/// Root window properties that change the window list.
+const ROOT_PROPERTIES: [&str; 4] = [
+    "_NET_CLIENT_LIST",
+    "_NET_ACTIVE_WINDOW",
+    "_NET_CURRENT_DESKTOP",
+    "_NET_NUMBER_OF_DESKTOPS",
+];
+
+/// Rendered window icons as base64 PNG; `None` when the window has none.
+type IconCache = Arc<Mutex<HashMap<Window, Option<String>>>>;
+
 pub struct X11Manager {
     conn: Arc<x11rb::rust_connection::RustConnection>,
     root: Window,
     atoms: HashMap<&'static str, Atom>, // Átomos cacheados
+    icon_cache: IconCache,
+    /// Pixel size window icons are rendered at
+    icon_size: u32,
+    /// Client windows whose property changes we listen to
+    watched_windows: HashSet<Window>,
 }
 
 impl X11Manager {
-    pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
+    pub fn new() -> Result<Self, WindowManagerError> {
         let (conn, screen_num) = x11rb::connect(None)?;
         let screen = &conn.setup().roots[screen_num];
         let root = screen.root;
@@ -48,6 +80,17 @@ impl X11Manager {
             "_NET_WM_WINDOW_TYPE_DROPDOWN_MENU",
             "_NET_WM_WINDOW_TYPE_POPUP_MENU",
             "WM_CLASS",
+            "_NET_WM_DESKTOP",
+            "_NET_WM_ICON",
+            "_NET_SUPPORTED",
+            "_NET_CLOSE_WINDOW",
+            "_NET_NUMBER_OF_DESKTOPS",
+            "_NET_CURRENT_DESKTOP",
+            "_NET_WM_STATE_MAXIMIZED_VERT",
+            "_NET_WM_STATE_MAXIMIZED_HORZ",
+            "_NET_WM_STATE_FULLSCREEN",
+            "_NET_WM_STATE_ABOVE",
+            "_NET_WM_STATE_STICKY",
         ];
 
         let mut atoms = HashMap::new();
@@ -60,6 +103,9 @@ impl X11Manager {
             conn: conn_arc,
             root,
             atoms,
+            icon_cache: IconCache::default(),
+            icon_size: DEFAULT_ICON_SIZE,
+            watched_windows: HashSet::new(),
         })
     }
 
@@ -141,6 +187,191 @@ impl X11Manager {
         Ok(String::new())
     }
 
+    /// Reads `_NET_WM_DESKTOP`; sticky windows (0xFFFFFFFF) have none.
+    fn get_window_desktop(&self, win: Window) -> Option<u32> {
+        let net_wm_desktop_atom = self.atoms.get("_NET_WM_DESKTOP")?;
+        let reply = self
+            .conn
+            .get_property(false, win, *net_wm_desktop_atom, AtomEnum::CARDINAL, 0, 1)
+            .ok()?
+            .reply()
+            .ok()?;
+
+        let desktop = reply.value32()?.next()?;
+        (desktop != u32::MAX).then_some(desktop)
+    }
+
+    fn get_number_of_desktops(&self) -> Result<u32, WindowManagerError> {
+        let net_number_of_desktops_atom = self
+            .atoms
+            .get("_NET_NUMBER_OF_DESKTOPS")
+            .ok_or("_NET_NUMBER_OF_DESKTOPS atom not found in cache")?;
+        let reply = self
+            .conn
+            .get_property(false, self.root, *net_number_of_desktops_atom, AtomEnum::CARDINAL, 0, 1)?
+            .reply()?;
+        Ok(reply.value32().and_then(|mut v| v.next()).unwrap_or(0))
+    }
+
+    /// Returns the window's own icon, rendering and caching it on first use.
+    fn get_window_icon(&self, win: Window) -> Option<String> {
+        if let Some(icon) = self.icon_cache.lock().ok()?.get(&win) {
+            return icon.clone();
+        }
+
+        let icon = self
+            .read_net_wm_icon(win)
+            .or_else(|| self.read_wm_hints_icon(win))
+            .and_then(|img| pixmap::encode_png_base64(&pixmap::fit_image(img, self.icon_size)).ok());
+
+        if let Ok(mut cache) = self.icon_cache.lock() {
+            cache.insert(win, icon.clone());
+        }
+        icon
+    }
+
+    /// Reads `_NET_WM_ICON`: a list of width, height and ARGB pixels, one
+    /// per size, from which the closest to the panel size is picked.
+    fn read_net_wm_icon(&self, win: Window) -> Option<RgbaImage> {
+        let net_wm_icon_atom = self.atoms.get("_NET_WM_ICON")?;
+        let reply = self
+            .conn
+            .get_property(false, win, *net_wm_icon_atom, AtomEnum::CARDINAL, 0, u32::MAX)
+            .ok()?
+            .reply()
+            .ok()?;
+
+        let values: Vec<u32> = reply.value32()?.collect();
+        let mut pixmaps: Vec<SniPixmap> = Vec::new();
+        let mut rest = values.as_slice();
+        while let [width, height, data @ ..] = rest {
+            let len = (*width as usize).checked_mul(*height as usize)?;
+            if len == 0 || len > data.len() {
+                break;
+            }
+
+            let argb = data[..len].iter().flat_map(|pixel| pixel.to_be_bytes()).collect();
+            pixmaps.push((*width as i32, *height as i32, argb));
+            rest = &data[len..];
+        }
+
+        pixmap::render_image(&pixmaps, self.icon_size)
+    }
+
+    /// Reads the legacy icon pixmap from `WM_HINTS`, applying its mask.
+    fn read_wm_hints_icon(&self, win: Window) -> Option<RgbaImage> {
+        let reply = self
+            .conn
+            .get_property(false, win, AtomEnum::WM_HINTS, AtomEnum::WM_HINTS, 0, 9)
+            .ok()?
+            .reply()
+            .ok()?;
+
+        let hints: Vec<u32> = reply.value32()?.collect();
+        let flags = *hints.first()?;
+        if flags & ICON_PIXMAP_HINT == 0 {
+            return None;
+        }
+
+        let mut img = self.read_pixmap(*hints.get(3)?)?;
+        if flags & ICON_MASK_HINT != 0 {
+            if let Some(mask) = hints.get(7).and_then(|mask| self.read_pixmap(*mask)) {
+                for (pixel, mask_pixel) in img.pixels_mut().zip(mask.pixels()) {
+                    // Bitmaps come out black where the bit is set
+                    pixel[3] = if mask_pixel[0] == 0 { 255 } else { 0 };
+                }
+            }
+        }
+
+        Some(img)
+    }
+
+    /// Fetches a pixmap as RGBA. Depth 1 bitmaps become black on white;
+    /// deeper ones must use 32 bits per pixel, as on any true-color visual.
+    fn read_pixmap(&self, pixmap: Pixmap) -> Option<RgbaImage> {
+        if pixmap == x11rb::NONE {
+            return None;
+        }
+
+        let geometry = self.conn.get_geometry(pixmap).ok()?.reply().ok()?;
+        let (width, height) = (u32::from(geometry.width), u32::from(geometry.height));
+        let image = self
+            .conn
+            .get_image(ImageFormat::Z_PIXMAP, pixmap, 0, 0, geometry.width, geometry.height, u32::MAX)
+            .ok()?
+            .reply()
+            .ok()?;
+
+        let setup = self.conn.setup();
+        let format = setup
+            .pixmap_formats
+            .iter()
+            .find(|format| format.depth == geometry.depth)?;
+        let pad = u32::from(format.scanline_pad).max(8);
+        let stride = (width * u32::from(format.bits_per_pixel)).div_ceil(pad) * pad / 8;
+
+        let mut img = RgbaImage::new(width, height);
+        for (x, y, pixel) in img.enumerate_pixels_mut() {
+            let row = (y * stride) as usize;
+            *pixel = if geometry.depth == 1 {
+                let byte = *image.data.get(row + (x / 8) as usize)?;
+                let bit = match setup.bitmap_format_bit_order {
+                    ImageOrder::MSB_FIRST => 7 - x % 8,
+                    _ => x % 8,
+                };
+                let value = if byte >> bit & 1 == 1 { 0 } else { 255 };
+                Rgba([value, value, value, 255])
+            } else if format.bits_per_pixel == 32 {
+                let offset = row + (x * 4) as usize;
+                let bytes = image.data.get(offset..offset + 4)?;
+                match setup.image_byte_order {
+                    ImageOrder::MSB_FIRST => Rgba([bytes[1], bytes[2], bytes[3], 255]),
+                    _ => Rgba([bytes[2], bytes[1], bytes[0], 255]),
+                }
+            } else {
+                return None;
+            };
+        }
+
+        Some(img)
+    }
+
+    /// Unminimizes a window if needed and gives it the focus, clearing the
+    /// urgency hint on the way.
+    fn restore_and_activate(&self, win: Window) -> Result<(), Box<dyn std::error::Error>> {
+        let net_wm_state_hidden_atom = *self
+            .atoms
+            .get("_NET_WM_STATE_HIDDEN")
+            .ok_or("_NET_WM_STATE_HIDDEN atom not found in cache")?;
+
+        if self.get_window_state(win)?.contains(&net_wm_state_hidden_atom) {
+            self.change_net_wm_state(win, 0, net_wm_state_hidden_atom)?;
+        }
+        if let Some(da_atom) = self.atoms.get("_NET_WM_STATE_DEMANDS_ATTENTION") {
+            self.change_net_wm_state(win, 0, *da_atom)?;
+        }
+        self.activate_window_ewmh(win)
+    }
+
+    /// Listens to property changes of client windows the first time they are
+    /// listed, and forgets windows that are gone.
+    fn watch_windows(&mut self, windows: &[Window]) {
+        self.watched_windows.retain(|win| windows.contains(win));
+        if let Ok(mut cache) = self.icon_cache.lock() {
+            cache.retain(|win, _| windows.contains(win));
+        }
+
+        let event_mask = EventMask::PROPERTY_CHANGE | EventMask::STRUCTURE_NOTIFY;
+        for win in windows {
+            if self.watched_windows.insert(*win) {
+                // Errors for windows destroyed meanwhile arrive as events and are ignored
+                let _ = self
+                    .conn
+                    .change_window_attributes(*win, &ChangeWindowAttributesAux::new().event_mask(event_mask));
+            }
+        }
+    }
+
     fn is_window_focused(&self, window: Window) -> Result<bool, Box<dyn std::error::Error>> {
         let net_active_window_atom = self
             .atoms
@@ -190,6 +421,71 @@ impl X11Manager {
         Ok(())
     }
 
+    /// Sends an EWMH client message about `win` to the root window, where
+    /// the window manager picks it up.
+    fn send_client_message(
+        &self,
+        win: Window,
+        message_type: &str,
+        data: [u32; 5],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let type_atom = self
+            .atoms
+            .get(message_type)
+            .ok_or_else(|| format!("{} atom not found in cache", message_type))?;
+
+        let event = ClientMessageEvent {
+            response_type: CLIENT_MESSAGE_EVENT,
+            format: 32,
+            sequence: 0,
+            window: win,
+            type_: *type_atom,
+            data: ClientMessageData::from(data),
+        };
+
+        self.conn.send_event(
+            false,
+            self.root,
+            EventMask::SUBSTRUCTURE_REDIRECT | EventMask::SUBSTRUCTURE_NOTIFY,
+            event,
+        )?;
+        Ok(())
+    }
+
+    /// Adds or removes up to two `_NET_WM_STATE` atoms in one request, as
+    /// maximizing needs both directions at once.
+    fn set_net_wm_state(&self, win_id: &str, enabled: bool, states: &[&str]) -> Result<(), WindowManagerError> {
+        let win = win_id.parse::<Window>()?;
+        let mut atoms = [0; 2];
+        for (atom, state) in atoms.iter_mut().zip(states) {
+            *atom = *self
+                .atoms
+                .get(state)
+                .ok_or_else(|| format!("{} atom not found in cache", state))?;
+        }
+
+        self.send_client_message(win, "_NET_WM_STATE", [u32::from(enabled), atoms[0], atoms[1], 2, 0])?;
+        self.conn.flush()?;
+        Ok(())
+    }
+
+    /// Atoms listed in the window manager's `_NET_SUPPORTED`.
+    fn get_supported_atoms(&self) -> Result<Vec<Atom>, Box<dyn std::error::Error>> {
+        let net_supported_atom = self
+            .atoms
+            .get("_NET_SUPPORTED")
+            .ok_or("_NET_SUPPORTED atom not found in cache")?;
+        let reply = self
+            .conn
+            .get_property(false, self.root, *net_supported_atom, AtomEnum::ATOM, 0, u32::MAX)?
+            .reply()?;
+
+        Ok(reply
+            .value32()
+            .map(|iter| iter.collect())
+            .unwrap_or_default())
+    }
+
     fn activate_window_ewmh(&self, window: Window) -> Result<(), Box<dyn std::error::Error>> {
         let net_active_window_atom = self
             .atoms
@@ -311,7 +607,7 @@ impl X11Manager {
 }
 
 impl WindowManagerBackend for X11Manager {
-    fn get_window_list(&mut self) -> Result<Vec<WindowInfo>, Box<dyn std::error::Error>> {
+    fn get_window_list(&mut self) -> Result<Vec<WindowInfo>, WindowManagerError> {
         // get_required_atoms ya no se llama aquí
         let net_client_list_atom = self
             .atoms
@@ -320,6 +616,7 @@ impl WindowManagerBackend for X11Manager {
         let net_wm_state_hidden_atom = self
             .atoms
             .get("_NET_WM_STATE_HIDDEN")
+            .copied()
             .ok_or("_NET_WM_STATE_HIDDEN atom not found in cache")?;
 
         let reply = self
@@ -336,6 +633,9 @@ impl WindowManagerBackend for X11Manager {
 
         let windows_prop: Vec<Window> =
             reply.value32().map_or_else(Vec::new, |iter| iter.collect());
+        self.watch_windows(&windows_prop);
+        let active_window: Option<Window> =
+            self.get_active_window().ok().flatten().and_then(|id| id.parse().ok());
         let mut window_list = Vec::new();
 
         for win in windows_prop {
@@ -368,6 +668,7 @@ impl WindowManagerBackend for X11Manager {
 
             let state = self.get_window_state(win)?; // Llama a la versión que usa self.atoms
             let class_name = self.get_window_class(win).unwrap_or_default();
+            let has_state = |name: &str| self.atoms.get(name).is_some_and(|atom| state.contains(atom));
 
             let demands_attention =
                 if let Some(da_atom) = self.atoms.get("_NET_WM_STATE_DEMANDS_ATTENTION") {
@@ -379,16 +680,27 @@ impl WindowManagerBackend for X11Manager {
             window_list.push(WindowInfo {
                 id: win.to_string(),
                 title,
-                is_minimized: state.contains(net_wm_state_hidden_atom),
-                icon: class_name,
+                is_minimized: state.contains(&net_wm_state_hidden_atom),
+                is_active: active_window == Some(win),
+                is_maximized: has_state("_NET_WM_STATE_MAXIMIZED_VERT")
+                    && has_state("_NET_WM_STATE_MAXIMIZED_HORZ"),
+                is_fullscreen: has_state("_NET_WM_STATE_FULLSCREEN"),
+                is_above: has_state("_NET_WM_STATE_ABOVE"),
+                is_sticky: has_state("_NET_WM_STATE_STICKY"),
+                icon: class_name.clone(),
+                icon_data: self.get_window_icon(win),
+                app_id: class_name,
+                desktop_id: None,
+                app_name: None,
                 demands_attention,
+                workspace: self.get_window_desktop(win),
             });
         }
 
         Ok(window_list)
     }
 
-    fn toggle_window(&self, win_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+    fn toggle_window(&self, win_id: &str) -> Result<(), WindowManagerError> {
         let window_to_toggle = win_id.parse::<Window>()?;
 
         let net_wm_state_hidden_atom =
@@ -448,9 +760,163 @@ impl WindowManagerBackend for X11Manager {
         Ok(())
     }
 
-    fn setup_event_monitoring(&mut self, tx: Sender<()>) -> Result<(), Box<dyn std::error::Error>> {
+    fn toggle_group(&self, win_ids: &[String]) -> Result<(), WindowManagerError> {
+        let net_wm_state_hidden_atom = self
+            .atoms
+            .get("_NET_WM_STATE_HIDDEN")
+            .copied()
+            .ok_or("_NET_WM_STATE_HIDDEN atom not found in cache")?;
+        let active_window = self.get_active_window()?;
+
+        let mut windows = Vec::new();
+        for win_id in win_ids {
+            let win = win_id.parse::<Window>()?;
+            let is_minimized = self.get_window_state(win)?.contains(&net_wm_state_hidden_atom);
+            windows.push((win_id.clone(), is_minimized, active_window.as_ref() == Some(win_id)));
+        }
+
+        match GroupToggle::decide(&windows) {
+            Some(GroupToggle::RestoreAll(ids)) => {
+                // The first window is activated last so it ends up on top
+                for win_id in ids.iter().rev() {
+                    self.restore_and_activate(win_id.parse()?)?;
+                }
+            }
+            Some(GroupToggle::Activate(win_id)) => self.restore_and_activate(win_id.parse()?)?,
+            Some(GroupToggle::Minimize(win_id)) => {
+                self.change_net_wm_state(win_id.parse()?, 1, net_wm_state_hidden_atom)?
+            }
+            None => {}
+        }
+
+        self.conn.flush()?;
+        Ok(())
+    }
+
+    fn capabilities(&self) -> WindowCapabilities {
+        let supported_atoms = self.get_supported_atoms().unwrap_or_default();
+        let supported = |names: &[&str]| {
+            names.iter().all(|name| {
+                self.atoms
+                    .get(name)
+                    .is_some_and(|atom| supported_atoms.contains(atom))
+            })
+        };
+
+        WindowCapabilities {
+            close: supported(&["_NET_CLOSE_WINDOW"]),
+            maximize: supported(&["_NET_WM_STATE_MAXIMIZED_VERT", "_NET_WM_STATE_MAXIMIZED_HORZ"]),
+            fullscreen: supported(&["_NET_WM_STATE_FULLSCREEN"]),
+            // Minimizing works like in toggle_window, through _NET_WM_STATE_HIDDEN
+            minimize: supported(&["_NET_WM_STATE"]),
+            always_on_top: supported(&["_NET_WM_STATE_ABOVE"]),
+            sticky: supported(&["_NET_WM_STATE_STICKY"]),
+            move_to_desktop: supported(&["_NET_WM_DESKTOP", "_NET_NUMBER_OF_DESKTOPS"]),
+            desktops: self.get_number_of_desktops().ok().filter(|desktops| *desktops > 0),
+        }
+    }
+
+    fn close_window(&self, win_id: &str) -> Result<(), WindowManagerError> {
+        let win = win_id.parse::<Window>()?;
+        // Source indication 2: the request comes from a pager
+        self.send_client_message(win, "_NET_CLOSE_WINDOW", [CURRENT_TIME, 2, 0, 0, 0])?;
+        self.conn.flush()?;
+        Ok(())
+    }
+
+    fn set_maximized(&self, win_id: &str, maximized: bool) -> Result<(), WindowManagerError> {
+        self.set_net_wm_state(
+            win_id,
+            maximized,
+            &["_NET_WM_STATE_MAXIMIZED_VERT", "_NET_WM_STATE_MAXIMIZED_HORZ"],
+        )
+    }
+
+    fn set_fullscreen(&self, win_id: &str, fullscreen: bool) -> Result<(), WindowManagerError> {
+        self.set_net_wm_state(win_id, fullscreen, &["_NET_WM_STATE_FULLSCREEN"])
+    }
+
+    fn set_minimized(&self, win_id: &str, minimized: bool) -> Result<(), WindowManagerError> {
+        if minimized {
+            self.set_net_wm_state(win_id, true, &["_NET_WM_STATE_HIDDEN"])
+        } else {
+            self.restore_and_activate(win_id.parse()?)?;
+            self.conn.flush()?;
+            Ok(())
+        }
+    }
+
+    fn set_always_on_top(&self, win_id: &str, above: bool) -> Result<(), WindowManagerError> {
+        self.set_net_wm_state(win_id, above, &["_NET_WM_STATE_ABOVE"])
+    }
+
+    fn set_sticky(&self, win_id: &str, sticky: bool) -> Result<(), WindowManagerError> {
+        self.set_net_wm_state(win_id, sticky, &["_NET_WM_STATE_STICKY"])
+    }
+
+    fn move_to_desktop(&self, win_id: &str, desktop: u32) -> Result<(), WindowManagerError> {
+        let win = win_id.parse::<Window>()?;
+
+        let desktops = self.get_number_of_desktops()?;
+        if desktop >= desktops {
+            return Err(WindowManagerError::Backend(format!(
+                "El escritorio {} no existe (hay {})",
+                desktop, desktops
+            )));
+        }
+
+        self.send_client_message(win, "_NET_WM_DESKTOP", [desktop, 2, 0, 0, 0])?;
+        self.conn.flush()?;
+        Ok(())
+    }
+
+    fn set_icon_size(&mut self, size: u32) {
+        let size = size.clamp(16, 256);
+        if size != self.icon_size {
+            self.icon_size = size;
+            if let Ok(mut cache) = self.icon_cache.lock() {
+                cache.clear();
+            }
+        }
+    }
+
+    fn get_active_window(&self) -> Result<Option<String>, WindowManagerError> {
+        let net_active_window_atom = self
+            .atoms
+            .get("_NET_ACTIVE_WINDOW")
+            .ok_or("_NET_ACTIVE_WINDOW atom not found in cache")?;
+        let reply = self
+            .conn
+            .get_property(false, self.root, *net_active_window_atom, AtomEnum::WINDOW, 0, 1)?
+            .reply()?;
+
+        Ok(reply
+            .value32()
+            .and_then(|mut v| v.next())
+            .filter(|window| *window != x11rb::NONE)
+            .map(|window| window.to_string()))
+    }
+
+    fn setup_event_monitoring(&mut self, tx: Sender<()>) -> Result<(), WindowManagerError> {
         let conn_clone = self.conn.clone();
         let root_window = self.root;
+        let icon_cache = self.icon_cache.clone();
+        let watched_atoms: Vec<Atom> = WATCHED_PROPERTIES
+            .iter()
+            .filter_map(|name| self.atoms.get(name).copied())
+            .chain([AtomEnum::WM_HINTS.into()])
+            .collect();
+        let root_atoms: Vec<Atom> = ROOT_PROPERTIES
+            .iter()
+            .filter_map(|name| self.atoms.get(name).copied())
+            .collect();
+        let icon_atoms: Vec<Atom> = self
+            .atoms
+            .get("_NET_WM_ICON")
+            .copied()
+            .into_iter()
+            .chain([AtomEnum::WM_HINTS.into()])
+            .collect();
 
         let event_mask = EventMask::SUBSTRUCTURE_NOTIFY | EventMask::PROPERTY_CHANGE;
         conn_clone.change_window_attributes(
@@ -464,11 +930,26 @@ impl WindowManagerBackend for X11Manager {
                 match conn_clone.wait_for_event() {
                     Ok(event) => {
                         match event {
-                            Event::PropertyNotify(_ev) => {
+                            Event::PropertyNotify(ev) => {
+                                if icon_atoms.contains(&ev.atom) {
+                                    if let Ok(mut cache) = icon_cache.lock() {
+                                        cache.remove(&ev.window);
+                                    }
+                                }
+                                // Windows change many properties the panel doesn't show
+                                let shown = if ev.window == root_window { &root_atoms } else { &watched_atoms };
+                                if !shown.contains(&ev.atom) {
+                                    continue;
+                                }
+                                if tx.send(()).is_err() { break; }
+                            }
+                            Event::DestroyNotify(ev) => {
+                                if let Ok(mut cache) = icon_cache.lock() {
+                                    cache.remove(&ev.window);
+                                }
                                 if tx.send(()).is_err() { break; }
                             }
-                            Event::CreateNotify(_) // CreateNotify, DestroyNotify, etc. en hijos de root
-                            | Event::DestroyNotify(_) => {
+                            Event::CreateNotify(_) => { // CreateNotify en hijos de root
                                 if tx.send(()).is_err() { break; }
                             }
                             _ => {}